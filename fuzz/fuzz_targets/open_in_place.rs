@@ -0,0 +1,29 @@
+//! Decrypt arbitrary data as an entry's content, which must fail cleanly
+//! unless the data was encrypted with the journal's key.
+#![no_main]
+
+#[allow(dead_code)]
+#[path = "../../src/security.rs"]
+mod security;
+#[allow(dead_code)]
+#[path = "../../src/uuid.rs"]
+mod uuid;
+
+use libfuzzer_sys::fuzz_target;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+use security::{CredentialGuard, DataGuard, Record, RecordKind};
+
+/// The guard of a journal with a random key. Unlocking a journal is slow, so
+/// it is only done once.
+static GUARD: Lazy<Mutex<DataGuard>> = Lazy::new(|| {
+    let cred_guard = CredentialGuard::new([0; 16], "fuzz", b"password");
+    let encrypted_key = cred_guard.generate_encrypted_key().unwrap();
+    Mutex::new(cred_guard.try_decrypt_key(encrypted_key).unwrap())
+});
+
+fuzz_target!(|data: &[u8]| {
+    let record = Record::new(uuid::Uuid::NIL, RecordKind::Content, 0);
+    let _ = GUARD.lock().unwrap().open_in_place(&record, data.to_vec());
+});
@@ -0,0 +1,29 @@
+//! Decrypt arbitrary data as a key slot's key, which must fail cleanly unless
+//! the data was encrypted with the user's credentials.
+#![no_main]
+
+#[allow(dead_code)]
+#[path = "../../src/security.rs"]
+mod security;
+#[allow(dead_code)]
+#[path = "../../src/uuid.rs"]
+mod uuid;
+
+use libfuzzer_sys::fuzz_target;
+use std::sync::Mutex;
+
+use security::CredentialGuard;
+
+/// Deriving the key from the credentials is slow, so the guard is kept
+/// between runs. `try_decrypt_key` gives it back when it fails.
+static CRED_GUARD: Mutex<Option<CredentialGuard>> = Mutex::new(None);
+
+fn new_cred_guard() -> CredentialGuard {
+    CredentialGuard::new([0; 16], "fuzz", b"password")
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut cred_guard = CRED_GUARD.lock().unwrap();
+    let guard = cred_guard.take().unwrap_or_else(new_cred_guard);
+    *cred_guard = guard.try_decrypt_key(data.to_vec()).err();
+});
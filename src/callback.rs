@@ -1,38 +1,145 @@
 use super::{
-    common::{open_file_in_editor, Format, DATETIME_FORMAT},
-    config::Config,
-    db::{GuardedStore, Ided, Metadata, MetadataAndContent},
+    common::{
+        create_keyfile, format_datetime, move_path, open_file_in_editor, prompt_new_password,
+        prompt_username, prompt_yes_no, read_keyfile, Format, TimeZone,
+    },
+    config::{Config, ConfigOverrides, Setting, UnlockMode, SETTINGS},
+    db::{now_local, Draft, GuardedStore, Ided, Metadata, MetadataAndContent, Store},
+    migrate::migrate,
+    recovery::RecoveryKey,
+    registry::Registry,
+    security::{credential_secret, Cipher, CredentialGuard, DbSalt, Suite},
+    shamir::{self, Share},
+    template::render_template,
     uuid::Uuid,
+    workfile::WorkFile,
 };
 use anyhow::Context as _;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Where the content of a new entry comes from.
+#[derive(Debug, Clone)]
+pub enum EntrySource {
+    /// Write the entry in the user's editor, optionally starting from the named
+    /// template.
+    Editor { template: Option<String> },
+    /// Use the given text.
+    Message(String),
+    /// Read the entry from stdin.
+    Stdin,
+    /// Read the entry from the specified file.
+    File(std::path::PathBuf),
+}
+
+/// Whether the entry has no content worth saving.
+fn is_blank(entry: &str) -> bool {
+    entry.is_empty() || entry.chars().all(|c| c.is_whitespace())
+}
+
+/// Edit a draft in the user's editor.
+///
+/// Whenever the editor leaves the draft with new, non-blank content, the draft
+/// is saved to the store, even if the editor fails. That way it can be
+/// recovered if the entry is never saved.
+fn edit_draft(
+    cfg: &Config,
+    db: &mut GuardedStore,
+    id: Uuid,
+    draft: &mut Draft,
+) -> anyhow::Result<()> {
+    let mut temp = WorkFile::new(cfg)?;
+    temp.write(&draft.content)?;
+    let status = open_file_in_editor(cfg, temp.path());
+    let content = temp.read()?;
+    if content != draft.content {
+        *draft = Draft::new(draft.entry, content);
+        if !is_blank(&draft.content) {
+            db.write_draft(id, draft).context("Could not save draft")?;
+        }
+    }
+    if status.is_err() && db.has_draft(id) {
+        status.context(format!(
+            "The draft was saved. Run `jarida drafts resume {}` to recover it.",
+            id
+        ))
+    } else {
+        status
+    }
+}
+
+/// Save the draft as a journal entry and remove the draft.
+///
+/// A draft of an existing entry that is unchanged is not saved. If it is
+/// blank, the user is asked whether to delete the entry instead.
+fn save_draft(db: &mut GuardedStore, id: Uuid, draft: Draft) -> anyhow::Result<()> {
+    match draft.entry {
+        Some(entry) => {
+            let content = db.get_content(&[entry]).into_iter().next().unwrap().data?;
+            if draft.content == content {
+                println!("No changes. Journal entry {} was not modified.", entry);
+            } else if is_blank(&draft.content) {
+                if prompt_yes_no(&format!(
+                    "The entry is empty/blank. Delete journal entry {}?",
+                    entry
+                ))? {
+                    db.remove(entry).context("Could not delete journal entry")?;
+                    println!("Deleted journal entry {}.", entry);
+                } else {
+                    println!("Journal entry {} was not modified.", entry);
+                }
+            } else {
+                db.update(entry, now_local(), draft.content)
+                    .context("Could not save journal entry")?;
+            }
+        }
+        None => {
+            if is_blank(&draft.content) {
+                anyhow::bail!("Entry was empty/blank. No journal entry saved.");
+            }
+            db.insert(&Metadata::new(db.username), draft.content)
+                .context("Could not save journal entry")?;
+        }
+    }
+    db.remove_draft(id)
+}
 
 // Create a new entry.
-pub fn new_entry(cfg: &Config, db: &mut GuardedStore) -> anyhow::Result<()> {
+pub fn new_entry(cfg: &Config, db: &mut GuardedStore, source: EntrySource) -> anyhow::Result<()> {
     use std::io::Read;
 
     let metadata = Metadata::new(db.username);
-    let mut entry = String::new();
-    {
-        let temp = tempfile::NamedTempFile::new_in(
-            cfg.temp_dir
-                .as_ref()
-                .cloned()
-                .unwrap_or_else(std::env::temp_dir),
-        )?;
-        open_file_in_editor(cfg, temp.path())?;
-        std::fs::File::open(temp.path())
-            .context(format!(
-                "Could not open temp file: {}",
-                temp.path().display()
-            ))?
-            .read_to_string(&mut entry)
-            .context(format!(
-                "Failed to read from temp file: {}",
-                temp.path().display()
-            ))?;
-    }
-
-    if entry.is_empty() || entry.chars().all(|c| c.is_whitespace()) {
+    let entry = match source {
+        EntrySource::Editor { template } => {
+            let initial = match template {
+                Some(name) => render_template(cfg, &name, db.username)
+                    .context(format!("Could not use template {:?}", name))?,
+                None => String::new(),
+            };
+            let id = Uuid::random()?;
+            let initial = Zeroizing::new(initial);
+            let mut draft = Draft::new(None, initial.clone());
+            edit_draft(cfg, db, id, &mut draft)?;
+            if draft.content == initial {
+                anyhow::bail!("Entry was unchanged. No journal entry saved.");
+            }
+            return save_draft(db, id, draft);
+        }
+        EntrySource::Message(message) => Zeroizing::new(message),
+        EntrySource::Stdin => {
+            let mut entry = Zeroizing::new(String::new());
+            std::io::stdin()
+                .read_to_string(&mut entry)
+                .context("Failed to read entry from stdin")?;
+            entry
+        }
+        EntrySource::File(path) => Zeroizing::new(
+            std::fs::read_to_string(&path)
+                .context(format!("Failed to read entry from {}", path.display()))?,
+        ),
+    };
+
+    if is_blank(&entry) {
         anyhow::bail!("Entry was empty/blank. No journal entry saved.");
     }
     db.insert(&metadata, entry)
@@ -42,43 +149,72 @@ pub fn new_entry(cfg: &Config, db: &mut GuardedStore) -> anyhow::Result<()> {
 
 /// Edit the content of the specified entry.
 pub fn edit_entry(cfg: &Config, db: &mut GuardedStore, id: Uuid) -> anyhow::Result<()> {
-    use std::io::{Read, Write};
-
     let entry = db.get_content(&[id]).into_iter().next().unwrap();
-    let mut data = entry.data?;
-    let modified = time::OffsetDateTime::now_utc();
-    {
-        let temp = tempfile::NamedTempFile::new_in(
-            cfg.temp_dir
-                .as_ref()
-                .cloned()
-                .unwrap_or_else(std::env::temp_dir),
-        )?;
-        temp.as_file().write_all(data.as_bytes())?;
-        temp.as_file().sync_data()?;
-
-        open_file_in_editor(cfg, temp.path())?;
-
-        data.clear();
-        std::fs::File::open(temp.path())
-            .context(format!(
-                "Could not open temp file: {}",
-                temp.path().display()
-            ))?
-            .read_to_string(&mut data)
-            .context(format!(
-                "Failed to read from temp file: {}",
-                temp.path().display()
-            ))?;
-    }
-
-    db.update(entry.uuid, modified, data)
-        .context("Could not save journal entry")?;
+    let draft_id = Uuid::random()?;
+    let mut draft = Draft::new(Some(entry.uuid), entry.data?);
+    edit_draft(cfg, db, draft_id, &mut draft)?;
+    save_draft(db, draft_id, draft)
+}
+
+/// List all the drafts that have not been saved as journal entries.
+pub fn print_draft_list(cfg: &Config, db: &mut GuardedStore) -> anyhow::Result<()> {
+    for id in db.get_draft_ids()? {
+        let draft = db
+            .read_draft(id)
+            .context(format!("Could not read draft {}", id))?;
+        let first_line = draft
+            .content
+            .lines()
+            .find(|line| !is_blank(line))
+            .unwrap_or_default();
+        println!(
+            "[{}] {} {}: {}",
+            id,
+            format_datetime(draft.saved, TimeZone::Local, cfg.datetime_format())?,
+            draft
+                .entry
+                .map(|entry| format!("edit of {}", entry))
+                .unwrap_or_else(|| "new entry".to_string()),
+            first_line.chars().take(40).collect::<String>(),
+        );
+    }
     Ok(())
 }
 
+/// Let the user know if there are drafts that could be recovered.
+pub fn notify_about_drafts(db: &GuardedStore) -> anyhow::Result<()> {
+    let count = db.get_draft_ids()?.len();
+    if count > 0 {
+        eprintln!(
+            "There are {} unsaved draft(s). Run `jarida drafts list` to see them.",
+            count
+        );
+    }
+    Ok(())
+}
+
+/// Continue editing a draft and save it as a journal entry.
+pub fn resume_draft(cfg: &Config, db: &mut GuardedStore, id: Uuid) -> anyhow::Result<()> {
+    let mut draft = db.read_draft(id)?;
+    edit_draft(cfg, db, id, &mut draft)?;
+    save_draft(db, id, draft)
+}
+
+/// Permanently remove a draft.
+pub fn discard_draft(db: &mut GuardedStore, id: Uuid) -> anyhow::Result<()> {
+    if !db.has_draft(id) {
+        anyhow::bail!("Invalid draft id {}", id);
+    }
+    db.remove_draft(id)
+}
+
 /// Print the metadata and content of every entry in the database.
-pub fn print_all_entries(db: &mut GuardedStore, format: Format) -> anyhow::Result<()> {
+pub fn print_all_entries(
+    cfg: &Config,
+    db: &mut GuardedStore,
+    format: Format,
+    zone: TimeZone,
+) -> anyhow::Result<()> {
     let ids = db.get_uuids().context("Could not read entry ids")?;
     let (ok, err): (Vec<_>, Vec<_>) = db
         .get_metadata_and_content(&ids[..])
@@ -88,7 +224,7 @@ pub fn print_all_entries(db: &mut GuardedStore, format: Format) -> anyhow::Resul
         Format::Default => {
             for entry in ok {
                 let data = entry.data.unwrap();
-                print_metadata_and_content(entry.uuid, &data);
+                print_metadata_and_content(cfg, entry.uuid, &data, zone)?;
                 println!();
             }
         }
@@ -111,7 +247,13 @@ pub fn print_all_entries(db: &mut GuardedStore, format: Format) -> anyhow::Resul
 }
 
 /// Print the metadata and contents of the specified entry.
-pub fn print_entry(db: &mut GuardedStore, id: Uuid, format: Format) -> anyhow::Result<()> {
+pub fn print_entry(
+    cfg: &Config,
+    db: &mut GuardedStore,
+    id: Uuid,
+    format: Format,
+    zone: TimeZone,
+) -> anyhow::Result<()> {
     let entry = db
         .get_metadata_and_content(&[id])
         .into_iter()
@@ -119,7 +261,7 @@ pub fn print_entry(db: &mut GuardedStore, id: Uuid, format: Format) -> anyhow::R
         .unwrap();
     let data = entry.data?;
     match format {
-        Format::Default => print_metadata_and_content(entry.uuid, &data),
+        Format::Default => print_metadata_and_content(cfg, entry.uuid, &data, zone)?,
         Format::Toml => {
             let mut map = std::collections::HashMap::new();
             map.insert(entry.uuid, data);
@@ -130,10 +272,10 @@ pub fn print_entry(db: &mut GuardedStore, id: Uuid, format: Format) -> anyhow::R
 }
 
 /// List identifying metadata for every entry in the database.
-pub fn print_entry_list(db: &mut GuardedStore) -> anyhow::Result<()> {
+pub fn print_entry_list(cfg: &Config, db: &mut GuardedStore, zone: TimeZone) -> anyhow::Result<()> {
     let ids = db.get_uuids().context("Could not read entry ids")?;
     let (ok, err): (Vec<_>, Vec<_>) = db
-        .get_metadata(&*ids)
+        .get_metadata(&ids)
         .into_iter()
         .partition(|item| item.data.is_ok());
     for ided_meta in ok {
@@ -141,10 +283,7 @@ pub fn print_entry_list(db: &mut GuardedStore) -> anyhow::Result<()> {
         println!(
             "[{}] {}",
             ided_meta.uuid,
-            meta.created
-                .to_offset(time::UtcOffset::current_local_offset().unwrap())
-                .format(DATETIME_FORMAT)
-                .unwrap()
+            format_datetime(meta.created, zone, cfg.datetime_format())?
         );
     }
     if let Some(Ided { uuid, data: Err(e) }) = err.into_iter().next() {
@@ -158,57 +297,758 @@ pub fn print_entry_list(db: &mut GuardedStore) -> anyhow::Result<()> {
 }
 
 /// Print the specified entry metadata and content.
-fn print_metadata_and_content(uuid: Uuid, entry: &MetadataAndContent) {
+fn print_metadata_and_content(
+    cfg: &Config,
+    uuid: Uuid,
+    entry: &MetadataAndContent,
+    zone: TimeZone,
+) -> anyhow::Result<()> {
     let modified = entry.metadata.created != entry.metadata.modified;
+    let mut written = format_datetime(entry.metadata.created, zone, cfg.datetime_format())?;
+    if let (TimeZone::Written, Some(name)) = (zone, &entry.metadata.timezone) {
+        written = format!("{} ({})", written, name);
+    }
     println!(
         // The Uuid is 32 hexadecimal characters so 80 - 3 - 2 - 32 = 43
         r#"{:=<3} {} {:=<43}
 Author:   {}
 Written:  {}"#,
-        "",
-        uuid,
-        "",
-        entry.metadata.author,
-        entry
-            .metadata
-            .created
-            .to_offset(time::UtcOffset::current_local_offset().unwrap())
-            .format(DATETIME_FORMAT)
-            .unwrap(),
+        "", uuid, "", entry.metadata.author, written,
     );
     if modified {
-        println!(
-            "Modified: {}",
-            entry
-                .metadata
-                .modified
-                .to_offset(time::UtcOffset::current_local_offset().unwrap())
-                .format(DATETIME_FORMAT)
-                .unwrap()
-        );
+        let mut modified = format_datetime(entry.metadata.modified, zone, cfg.datetime_format())?;
+        match entry.metadata.modified_by {
+            Some(ref user) if *user != entry.metadata.author => {
+                modified = format!("{} by {}", modified, user);
+            }
+            _ => (),
+        }
+        println!("Modified: {}", modified);
     }
     println!("{:=<80}", "");
-    println!("{}", entry.content);
+    println!("{}", entry.content.as_str());
+    Ok(())
 }
 
 /// Try to initialize the specified directory. If `dir` is None, the user's home
 /// directory is assumed. If config directory already exists, an error is
-/// returned.
-pub fn init(dir: Option<std::path::PathBuf>) -> anyhow::Result<()> {
+/// returned. If a name is given, the new journal is added to the journal
+/// registry with that name.
+pub fn init(
+    overrides: &ConfigOverrides,
+    dir: Option<std::path::PathBuf>,
+    name: Option<String>,
+    cipher: Option<Cipher>,
+) -> anyhow::Result<()> {
     use std::io::Write as _;
 
-    let mut path = dir
+    let path = dir
         .ok_or_else(|| anyhow::anyhow!("")) // This error is never used, but must match that of get_user_config_dir_path.
         .map(|mut path| {
             path.push(Config::DIR_NAME);
             path
         })
         .or_else(|_| Config::get_user_config_dir_path())?;
-    if path.exists() {
+    let file_path = path.join(Config::FILE_NAME);
+    if file_path.exists() {
         anyhow::bail!("{} is already initialized", path.display());
     }
-    std::fs::create_dir(&path)?;
-    path.push(Config::FILE_NAME);
-    std::fs::File::create(path)?.write_all(Config::template().as_bytes())?;
+    std::fs::create_dir_all(&path)?;
+    std::fs::File::create(&file_path)?.write_all(Config::template().as_bytes())?;
+    if let Some(name) = name {
+        add_journal(&name, Some(path))?;
+    }
+    if let Some(cipher) = cipher {
+        let overrides = ConfigOverrides {
+            config: Some(file_path),
+            ..overrides.clone()
+        };
+        let data_path = Config::find(&overrides)?.data_store_path()?;
+        Store::open(&data_path)?.set_suite(Suite {
+            cipher,
+            ..Suite::default()
+        })?;
+        println!("{} will be encrypted with {}", data_path.display(), cipher);
+    }
+    Ok(())
+}
+
+/// List the journals in the registry and show which journal would be used.
+pub fn print_journal_list(overrides: &ConfigOverrides) -> anyhow::Result<()> {
+    let current = Config::find_config_file_path(overrides);
+    let current_dir = current.as_ref().ok().and_then(|(path, _)| path.parent());
+    let registry = Registry::load()?;
+    for (name, dir) in registry.iter() {
+        println!(
+            "{} {:<16} {}",
+            if Some(dir) == current_dir { "*" } else { " " },
+            name,
+            dir.display()
+        );
+    }
+    match current {
+        Ok((path, source)) => println!("\nIn use: {} ({})", path.display(), source),
+        Err(e) => println!("\nNo journal in use: {}", e),
+    }
+    Ok(())
+}
+
+/// Add a journal to the registry. If `dir` is None, the journal that would
+/// currently be used is added. Otherwise `dir` may be either the journal's
+/// configuration directory or the directory containing it.
+pub fn add_journal(name: &str, dir: Option<std::path::PathBuf>) -> anyhow::Result<()> {
+    let dir = match dir {
+        Some(dir) if dir.join(Config::DIR_NAME).is_dir() => dir.join(Config::DIR_NAME),
+        Some(dir) => dir,
+        None => Config::find_config_dir_path(None)?.0,
+    };
+    if !dir.join(Config::FILE_NAME).is_file() {
+        anyhow::bail!("{} does not contain a jarida config file", dir.display());
+    }
+    let dir = dir
+        .canonicalize()
+        .context(format!("Could not resolve {}", dir.display()))?;
+    let mut registry = Registry::load()?;
+    registry.insert(name.to_string(), dir.clone());
+    registry.save()?;
+    println!("Added journal {:?}: {}", name, dir.display());
+    Ok(())
+}
+
+/// Remove a journal from the registry.
+pub fn remove_journal(name: &str) -> anyhow::Result<()> {
+    let mut registry = Registry::load()?;
+    let dir = registry.remove(name)?;
+    registry.save()?;
+    println!("Removed journal {:?}: {}", name, dir.display());
     Ok(())
 }
+
+/// Create a guard for the user's new credentials. If the keyfile does not
+/// exist, it is created with random contents.
+fn new_credential_guard(
+    db: &Store,
+    username: &str,
+    password: Option<&str>,
+    keyfile: Option<&std::path::Path>,
+) -> anyhow::Result<CredentialGuard> {
+    let keyfile_contents = match keyfile {
+        Some(keyfile) => {
+            if !keyfile.exists() {
+                create_keyfile(keyfile)?;
+                println!(
+                    "Created keyfile {}. Keep a copy in a safe place.",
+                    keyfile.display()
+                );
+            }
+            Some(read_keyfile(keyfile)?)
+        }
+        None => None,
+    };
+    Ok(CredentialGuard::new(
+        get_db_salt(db)?,
+        username,
+        &credential_secret(password, keyfile_contents.as_deref().map(Vec::as_slice)),
+    ))
+}
+
+/// Get the database's unique salt.
+fn get_db_salt(db: &Store) -> anyhow::Result<DbSalt> {
+    use std::convert::TryInto as _;
+
+    db.get_salt()?
+        .as_slice()
+        .try_into()
+        .context("Salt is the wrong size")
+}
+
+/// Change the password or what is needed to unlock the journal, and update
+/// the config file to match.
+///
+/// With `add_keyfile`, the keyfile is required from now on, either together
+/// with the password or, with `keyfile_only`, on its own. With
+/// `remove_keyfile`, only the password is required.
+pub fn change_credentials(
+    cfg: &Config,
+    db: &mut GuardedStore,
+    add_keyfile: Option<std::path::PathBuf>,
+    keyfile_only: bool,
+    remove_keyfile: bool,
+) -> anyhow::Result<()> {
+    let path = cfg
+        .path
+        .clone()
+        .context("There is no config file to record the change in")?;
+    let (mode, keyfile) = match add_keyfile {
+        Some(keyfile) if keyfile_only => (
+            UnlockMode::Keyfile,
+            Some(std::env::current_dir()?.join(keyfile)),
+        ),
+        Some(keyfile) => (
+            UnlockMode::PasswordAndKeyfile,
+            Some(std::env::current_dir()?.join(keyfile)),
+        ),
+        None if remove_keyfile => (UnlockMode::Password, None),
+        None if cfg.unlock_mode().uses_keyfile() => (cfg.unlock_mode(), cfg.keyfile.clone()),
+        None => (UnlockMode::Password, None),
+    };
+
+    let password = if mode.uses_password() {
+        Some(prompt_new_password()?)
+    } else {
+        None
+    };
+    let username = db.username;
+    let cred_guard = new_credential_guard(
+        db.store,
+        username,
+        password.as_deref().map(String::as_str),
+        keyfile.as_deref(),
+    )?;
+
+    // Keep the old key slots so they can be restored if config can't be
+    // updated to match, which would leave the journal locked.
+    let old_slots = db.store.get_key_slots()?;
+    db.set_key_slot(username, &cred_guard)?;
+    let updated = (|| -> anyhow::Result<()> {
+        if let Some(ref keyfile) = keyfile {
+            Config::set_in_file(&path, "keyfile", &keyfile.to_string_lossy())?;
+        }
+        Config::set_in_file(&path, "unlock", &mode.to_string())?;
+        if let (Some(password), Some(_)) = (&password, &cfg.password) {
+            Config::set_in_file(&path, "password", password)?;
+        }
+        Ok(())
+    })();
+    if let Err(e) = updated {
+        db.store.set_key_slots(&old_slots)?;
+        return Err(e.context("The credentials were not changed"));
+    }
+
+    println!("The journal is now unlocked with: {}", mode);
+    if mode.uses_password() && (cfg.password_command.is_some() || cfg.password_file.is_some()) {
+        println!("Remember to update the password given by password_command or password_file.");
+    }
+    Ok(())
+}
+
+/// Print the users who can unlock the journal.
+pub fn print_access_list(cfg: &Config, db: &mut GuardedStore) -> anyhow::Result<()> {
+    for slot in db.store.get_key_slots()? {
+        let user = match slot.user {
+            _ if slot.recovery => "(recovery key)",
+            Some(ref user) => user,
+            None => "(unknown)",
+        };
+        let added = match slot.added {
+            Some(added) => format_datetime(added, TimeZone::Local, cfg.date_format())?,
+            None => String::new(),
+        };
+        println!(
+            "{} {:<16} {}",
+            if user == db.username { "*" } else { " " },
+            user,
+            added
+        );
+    }
+    Ok(())
+}
+
+/// Let another user unlock the journal with their own credentials. With
+/// `keyfile`, the keyfile is required in addition to their password or, with
+/// `keyfile_only`, instead of it.
+pub fn grant_access(
+    db: &mut GuardedStore,
+    user: &str,
+    keyfile: Option<std::path::PathBuf>,
+    keyfile_only: bool,
+) -> anyhow::Result<()> {
+    if db
+        .store
+        .get_key_slots()?
+        .iter()
+        .any(|slot| slot.user.as_deref() == Some(user))
+    {
+        anyhow::bail!(
+            "{} can already unlock the journal. They can change their password with `jarida passwd`.",
+            user
+        );
+    }
+    let keyfile = match keyfile {
+        Some(keyfile) => Some(std::env::current_dir()?.join(keyfile)),
+        None => None,
+    };
+    let mode = match (&keyfile, keyfile_only) {
+        (Some(_), true) => UnlockMode::Keyfile,
+        (Some(_), false) => UnlockMode::PasswordAndKeyfile,
+        (None, _) => UnlockMode::Password,
+    };
+    let password = if mode.uses_password() {
+        println!(
+            "{} will need to enter this password to unlock the journal.",
+            user
+        );
+        Some(prompt_new_password()?)
+    } else {
+        None
+    };
+    let cred_guard = new_credential_guard(
+        db.store,
+        user,
+        password.as_deref().map(String::as_str),
+        keyfile.as_deref(),
+    )?;
+    db.set_key_slot(user, &cred_guard)?;
+    println!(
+        "{} can now unlock the journal with: {}. Their config needs `user = {:?}` and `unlock = \"{}\"`.",
+        user, mode, user, mode
+    );
+    Ok(())
+}
+
+/// Stop a user from unlocking the journal.
+pub fn revoke_access(db: &mut GuardedStore, user: &str) -> anyhow::Result<()> {
+    let mut slots = db.store.get_key_slots()?;
+    let count = slots.len();
+    slots.retain(|slot| slot.user.as_deref() != Some(user));
+    if slots.len() == count {
+        anyhow::bail!("{} can't unlock the journal", user);
+    }
+    if slots.iter().all(|slot| slot.recovery) {
+        anyhow::bail!("{} is the only user who can unlock the journal", user);
+    }
+    if user == db.username
+        && !prompt_yes_no("You will no longer be able to unlock the journal. Continue?")?
+    {
+        return Ok(());
+    }
+    db.store.set_key_slots(&slots)?;
+    println!("{} can no longer unlock the journal.", user);
+    println!("Note that they may have kept a copy of the journal's key, which does not change.");
+    Ok(())
+}
+
+/// Create a recovery key that can unlock the journal if a password or keyfile
+/// is lost. Any previous recovery key stops working.
+///
+/// With `shares`, the recovery key is split into that many shares instead,
+/// any `threshold` of which can rebuild it. The shares are printed or, with
+/// `out_dir`, written to files in that directory.
+pub fn create_recovery_key(
+    db: &mut GuardedStore,
+    shares: Option<(u8, u8)>,
+    out_dir: Option<std::path::PathBuf>,
+) -> anyhow::Result<()> {
+    if db.store.get_key_slots()?.iter().any(|slot| slot.recovery)
+        && !prompt_yes_no(
+            "The journal already has a recovery key, which will stop working. Continue?",
+        )?
+    {
+        return Ok(());
+    }
+    let key = RecoveryKey::random()?;
+    let cred_guard = CredentialGuard::new(get_db_salt(db.store)?, RecoveryKey::USER, key.secret());
+    // Only change the journal once the shares are safely written.
+    if let Some((count, threshold)) = shares {
+        let shares = shamir::split(key.secret(), threshold, count)?;
+        match out_dir {
+            Some(dir) => {
+                std::fs::create_dir_all(&dir)
+                    .context(format!("Could not create {}", dir.display()))?;
+                // Each share must only be readable by the user it is for.
+                let mut options = std::fs::OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::OpenOptionsExt as _;
+                    options.mode(0o600);
+                }
+                for share in &shares {
+                    let path = dir.join(format!("share-{}.txt", share.number()));
+                    let contents = Zeroizing::new(format!(
+                        "# Share {} of {} of a jarida recovery key. Any {} of them can unlock the journal.\n{}\n",
+                        share.number(),
+                        count,
+                        threshold,
+                        share
+                    ));
+                    options
+                        .open(&path)
+                        .and_then(|mut file| {
+                            use std::io::Write as _;
+                            file.write_all(contents.as_bytes())
+                        })
+                        .context(format!("Could not write {}", path.display()))?;
+                    println!("Wrote share {} to {}", share.number(), path.display());
+                }
+            }
+            None => {
+                for share in &shares {
+                    println!("Share {} of {}:\n\n    {}\n", share.number(), count, share);
+                }
+            }
+        }
+        db.set_recovery_key_slot(&cred_guard)?;
+        println!(
+            "Give each share to a different person you trust. Any {} of them can unlock the",
+            threshold
+        );
+        println!("journal with `jarida recover --shares`. The shares will not be shown again.");
+        return Ok(());
+    }
+    db.set_recovery_key_slot(&cred_guard)?;
+    println!("The journal's recovery key is:\n\n    {}\n", key);
+    println!("Write it down and keep it somewhere safe. It will not be shown again.");
+    println!("Anyone who has it can unlock the journal. If you forget your password or lose");
+    println!("your keyfile, run `jarida recover` and enter it to set new credentials.");
+    Ok(())
+}
+
+/// Remove the journal's recovery key.
+pub fn remove_recovery_key(db: &mut GuardedStore) -> anyhow::Result<()> {
+    let mut slots = db.store.get_key_slots()?;
+    let count = slots.len();
+    slots.retain(|slot| !slot.recovery);
+    if slots.len() == count {
+        anyhow::bail!("The journal has no recovery key");
+    }
+    db.store.set_key_slots(&slots)?;
+    println!("Removed the recovery key");
+    Ok(())
+}
+
+/// Encrypt all journal data again with the next generation of keys derived
+/// from the journal's key and, optionally, another cipher.
+pub fn reencrypt(db: &mut GuardedStore, cipher: Option<Cipher>) -> anyhow::Result<()> {
+    let current = db.store.get_suite()?;
+    let suite = Suite {
+        cipher: cipher.unwrap_or(current.cipher),
+        key_generation: current
+            .key_generation
+            .checked_add(1)
+            .context("There are no more key generations to encrypt the journal with")?,
+    };
+    db.set_suite(suite)?;
+    println!(
+        "Encrypted all entries and drafts with key generation {} and {}",
+        suite.key_generation, suite.cipher
+    );
+    Ok(())
+}
+
+/// Read shares of the recovery key from the files, then prompt for more until
+/// there are enough to rebuild it.
+fn read_recovery_key_shares(files: &[std::path::PathBuf]) -> anyhow::Result<RecoveryKey> {
+    use std::io::BufRead as _;
+    use std::io::Write as _;
+
+    let mut shares = Vec::new();
+    for path in files {
+        let contents = Zeroizing::new(
+            std::fs::read_to_string(path).context(format!("Could not read {}", path.display()))?,
+        );
+        let share: Share = contents
+            .parse()
+            .context(format!("Invalid share in {}", path.display()))?;
+        shares.push(share);
+    }
+    loop {
+        let mut numbers: Vec<_> = shares.iter().map(Share::number).collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+        match shares.first() {
+            Some(share) if numbers.len() >= share.threshold() as usize => break,
+            Some(share) => println!("{} of {} shares entered", numbers.len(), share.threshold()),
+            None => (),
+        }
+        print!("Share (words separated by spaces): ");
+        std::io::stdout().flush()?;
+        let line = Zeroizing::new(
+            std::io::stdin()
+                .lock()
+                .lines()
+                .next()
+                .context("No more shares were given")??,
+        );
+        match line.parse::<Share>() {
+            Ok(share) => shares.push(share),
+            Err(e) => println!("{:#}. Try again.", e),
+        }
+    }
+    RecoveryKey::from_bytes(&shamir::combine(&shares)?)
+}
+
+/// Unlock the journal with its recovery key and set new credentials for the
+/// user, as they are specified in config. A missing keyfile is created anew.
+///
+/// With `shares`, the recovery key is rebuilt from shares, read from the
+/// files and entered by the user.
+pub fn recover(
+    cfg: &Config,
+    db: &mut Store,
+    shares: Option<&[std::path::PathBuf]>,
+) -> anyhow::Result<()> {
+    let slot = db
+        .get_key_slots()?
+        .into_iter()
+        .find(|slot| slot.recovery)
+        .context("The journal has no recovery key")?;
+    let key = match shares {
+        Some(files) => read_recovery_key_shares(files)?,
+        None => Zeroizing::new(
+            rpassword::prompt_password_stdout("Recovery key: ")
+                .context("Error getting the recovery key")?,
+        )
+        .parse()?,
+    };
+    let cred_guard = CredentialGuard::new(get_db_salt(db)?, RecoveryKey::USER, key.secret());
+    let mut data_guard = cred_guard
+        .try_decrypt_key(slot.key)
+        .map_err(|_| anyhow::anyhow!("The recovery key does not unlock this journal"))?;
+
+    let username = match cfg.user {
+        Some(ref user) => user.clone(),
+        None => prompt_username()?,
+    };
+    let mode = cfg.unlock_mode();
+    let password = if mode.uses_password() {
+        Some(prompt_new_password()?)
+    } else {
+        None
+    };
+    let keyfile = if mode.uses_keyfile() {
+        cfg.keyfile.as_deref()
+    } else {
+        None
+    };
+    let mut db = db.guard(&mut data_guard, &username);
+    let cred_guard = new_credential_guard(
+        db.store,
+        &username,
+        password.as_deref().map(String::as_str),
+        keyfile,
+    )?;
+    db.set_key_slot(&username, &cred_guard)?;
+    if let (Some(password), Some(path), Some(_)) = (&password, &cfg.path, &cfg.password) {
+        Config::set_in_file(path, "password", password)?;
+    }
+    println!("{} can unlock the journal again with: {}", username, mode);
+    Ok(())
+}
+
+/// Format a setting's value for display.
+fn display_setting_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Print every setting with its value and where the value came from.
+pub fn print_config_list(overrides: &ConfigOverrides) -> anyhow::Result<()> {
+    let cfg = Config::find(overrides)?;
+    println!("# {} ({})", cfg.dir.display(), cfg.source);
+    for setting in SETTINGS {
+        match cfg.get(setting.key)? {
+            Some(value) => {
+                let value = if setting.key == "password" {
+                    "********".to_string()
+                } else {
+                    display_setting_value(&value)
+                };
+                println!(
+                    "{} = {} ({})",
+                    setting.key,
+                    value,
+                    cfg.get_origin(setting.key)?
+                );
+            }
+            None => println!("{} is not set ({})", setting.key, setting.description),
+        }
+    }
+    Ok(())
+}
+
+/// Print the value of a setting.
+pub fn print_config_value(overrides: &ConfigOverrides, key: &str) -> anyhow::Result<()> {
+    let cfg = Config::find(overrides)?;
+    match cfg.get(key)? {
+        Some(value) => println!("{}", display_setting_value(&value)),
+        None => anyhow::bail!("{} is not set", Setting::find(key)?.key),
+    }
+    Ok(())
+}
+
+/// Change a setting in the config file.
+pub fn set_config_value(overrides: &ConfigOverrides, key: &str, value: &str) -> anyhow::Result<()> {
+    let (path, _) = Config::find_config_file_path(overrides)?;
+    Config::set_in_file(&path, key, value)?;
+    println!("Set {} in {}", Setting::find(key)?.key, path.display());
+    Ok(())
+}
+
+/// Edit the config file in the editor. The changes are only saved if the
+/// result is a valid config.
+pub fn edit_config(overrides: &ConfigOverrides) -> anyhow::Result<()> {
+    let (path, _) = Config::find_config_file_path(overrides)?;
+    let original = std::fs::read_to_string(&path)
+        .context(format!("Could not read config {}", path.display()))?;
+    // The config may be invalid, in which case it can't be used to find the
+    // editor, but it still needs fixing.
+    let cfg = Config::find(overrides).unwrap_or_else(|_| Config {
+        editor: overrides.editor.clone(),
+        ..Config::default()
+    });
+    let mut file = WorkFile::new(&cfg)?;
+    file.write(&original)?;
+    loop {
+        open_file_in_editor(&cfg, file.path())?;
+        let contents = file.read()?;
+        if *contents == original {
+            println!("No changes to save");
+            return Ok(());
+        }
+        match contents.parse::<Config>() {
+            Ok(_) => {
+                std::fs::write(&path, contents)
+                    .context(format!("Could not write config {}", path.display()))?;
+                println!("Saved {}", path.display());
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                if !prompt_yes_no("The config is invalid. Edit it again?")? {
+                    anyhow::bail!("Discarded the changes to {}", path.display());
+                }
+            }
+        }
+    }
+}
+
+/// Check that the config file is valid.
+pub fn validate_config(overrides: &ConfigOverrides) -> anyhow::Result<()> {
+    let (path, _) = Config::find_config_file_path(overrides)?;
+    let contents = std::fs::read_to_string(&path)
+        .context(format!("Could not read config {}", path.display()))?;
+    contents
+        .parse::<Config>()
+        .context(format!("{} is invalid", path.display()))?;
+    println!("{} is valid", path.display());
+    Ok(())
+}
+
+/// Move the user's config and journal data from the legacy `.jarida` directory
+/// in the user's home directory to the XDG base directories.
+pub fn migrate_to_xdg() -> anyhow::Result<()> {
+    if !cfg!(target_os = "linux") {
+        anyhow::bail!("The XDG base directories are only used on Linux");
+    }
+    let legacy_dir = Config::get_legacy_user_config_dir_path()?;
+    let config_dir = Config::get_user_config_dir_path()?;
+    let data_dir = Config::get_user_data_dir_path()?;
+    if !legacy_dir.join(Config::FILE_NAME).exists() {
+        anyhow::bail!("There is no config in {} to migrate", legacy_dir.display());
+    }
+    if config_dir.join(Config::FILE_NAME).exists() {
+        anyhow::bail!("{} already contains a config file", config_dir.display());
+    }
+
+    // Journal data is only in the legacy directory if config does not put it
+    // somewhere else.
+    let cfg = Config::find(&ConfigOverrides {
+        config: Some(legacy_dir.join(Config::FILE_NAME)),
+        ..ConfigOverrides::default()
+    })?;
+    let move_data = cfg.journal_dir.is_none();
+    if move_data && data_dir.exists() && std::fs::read_dir(&data_dir)?.next().is_some() {
+        anyhow::bail!("{} is not empty", data_dir.display());
+    }
+
+    std::fs::create_dir_all(&config_dir)?;
+    if move_data {
+        std::fs::create_dir_all(&data_dir)?;
+    }
+    // Move everything back if anything can't be moved, so the config and
+    // journal data are never left split between the directories.
+    let mut moved = Vec::new();
+    if let Err(error) = move_legacy_dir(&legacy_dir, &config_dir, &data_dir, move_data, &mut moved)
+    {
+        for (from, to) in moved.iter().rev() {
+            if let Err(undo_error) = move_path(to, from) {
+                eprintln!(
+                    "Warning: Could not move {} back to {}: {:#}",
+                    to.display(),
+                    from.display(),
+                    undo_error
+                );
+            }
+        }
+        return Err(error);
+    }
+    std::fs::remove_dir(&legacy_dir)?;
+
+    let mut registry = Registry::load()?;
+    registry.relocate(&legacy_dir, &config_dir);
+    registry.save()?;
+
+    println!("Moved config to {}", config_dir.display());
+    if move_data {
+        println!("Moved journal data to {}", data_dir.display());
+    }
+    Ok(())
+}
+
+/// Move everything in the legacy config directory to the XDG config directory,
+/// or journal data to the XDG data directory if `move_data` is set. Each move
+/// that was made is added to `moved`, so they can be undone.
+fn move_legacy_dir(
+    legacy_dir: &Path,
+    config_dir: &Path,
+    data_dir: &Path,
+    move_data: bool,
+    moved: &mut Vec<(PathBuf, PathBuf)>,
+) -> anyhow::Result<()> {
+    let entries = std::fs::read_dir(legacy_dir)?.collect::<Result<Vec<_>, _>>()?;
+    for entry in entries {
+        let name = entry.file_name();
+        let is_config = [
+            Config::FILE_NAME,
+            Config::TEMPLATES_DIR_NAME,
+            Config::PROMPTS_FILE_NAME,
+            Registry::FILE_NAME,
+        ]
+        .iter()
+        .any(|config_name| name == *config_name);
+        let to = if is_config || !move_data {
+            config_dir.join(&name)
+        } else {
+            data_dir.join(&name)
+        };
+        move_path(&entry.path(), &to).context(format!(
+            "Could not move {} to {}",
+            entry.path().display(),
+            to.display()
+        ))?;
+        moved.push((entry.path(), to));
+    }
+    Ok(())
+}
+
+/// Upgrade the store to the current on-disk format, backing it up first.
+pub fn upgrade(db: &mut GuardedStore) -> anyhow::Result<()> {
+    use std::cmp::Ordering;
+    match db.store.version().cmp(&Store::CURRENT_VERSION) {
+        Ordering::Equal => {
+            println!("Already up to date (v{}).", Store::CURRENT_VERSION);
+            Ok(())
+        }
+        Ordering::Greater => db.store.check_version(),
+        Ordering::Less => {
+            let backup = db.store.backup()?;
+            println!("Backed up to {}", backup.display());
+            migrate(db)?;
+            println!("Upgraded to v{}.", Store::CURRENT_VERSION);
+            Ok(())
+        }
+    }
+}
@@ -1,8 +1,16 @@
 use super::{
-    callback::{edit_entry, init, new_entry, print_all_entries, print_entry, print_entry_list},
-    common::Format,
-    config::Config,
-    db::GuardedStore,
+    callback::{
+        add_journal, change_credentials, create_recovery_key, discard_draft, edit_config,
+        edit_entry, grant_access, init, migrate_to_xdg, new_entry, notify_about_drafts,
+        print_access_list, print_all_entries, print_config_list, print_config_value,
+        print_draft_list, print_entry, print_entry_list, print_journal_list, recover, reencrypt,
+        remove_journal, remove_recovery_key, resume_draft, revoke_access, set_config_value,
+        upgrade, validate_config, EntrySource,
+    },
+    common::{get_and_validate_credentials, Format, TimeZone},
+    config::{Config, ConfigOverrides},
+    db::{GuardedStore, Store},
+    security::Cipher,
     uuid::Uuid,
 };
 use std::path::PathBuf;
@@ -10,16 +18,90 @@ use std::path::PathBuf;
 #[derive(Debug, clap::Parser)]
 #[clap(version, about, long_about = None)]
 pub struct Args {
+    #[clap(flatten)]
+    overrides: ConfigOverrides,
     #[clap(subcommand)]
     action: Action,
 }
 
 #[derive(Debug, clap::Subcommand)]
 pub enum Action {
+    #[clap(flatten)]
+    Store(StoreAction),
+    /// Unlock the journal with its recovery key and set new credentials
+    ///
+    /// The new credentials are for the user and unlock mode in config.
+    Recover {
+        /// Rebuild the recovery key from shares instead of entering it. The
+        /// shares are read from the files given, then entered one at a time
+        /// until there are enough.
+        #[clap(long, value_name = "FILE", min_values = 0)]
+        shares: Option<Vec<PathBuf>>,
+    },
+    /// Initialize the system
+    Init {
+        /// The directory to use for program data. If omitted, a directory will be created in the user's home directory.
+        dir: Option<PathBuf>,
+        /// Add the new journal to the journal registry with this name
+        #[clap(long)]
+        name: Option<String>,
+        /// The cipher to encrypt the journal with. If given, the journal's
+        /// data is created right away; otherwise it is created on first use
+        /// with AES-256-GCM.
+        #[clap(long, possible_values = ["aes-256-gcm", "chacha20-poly1305"])]
+        cipher: Option<Cipher>,
+    },
+    /// Move the config and journal data in ~/.jarida to the XDG base
+    /// directories ($XDG_CONFIG_HOME/jarida and $XDG_DATA_HOME/jarida)
+    ///
+    /// This only needs to be done once, and only on Linux.
+    MigrateXdg,
+    /// List the journals in the journal registry and show which journal is in
+    /// use
+    Journals {
+        #[clap(subcommand)]
+        action: Option<JournalAction>,
+    },
+    /// Show and change settings in the config file
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+/// A command that works with the journal data once it has been unlocked
+#[derive(Debug, clap::Subcommand)]
+pub enum StoreAction {
     /// Create a new journal entry
-    New,
+    ///
+    /// The entry is written in your editor unless its content is given on the
+    /// command line, in a file or on stdin. When reading from stdin, your
+    /// credentials must be specified in config.
+    New {
+        /// Pass `-` to read the entry from stdin
+        #[clap(possible_values = ["-"], conflicts_with_all = &["message", "file"])]
+        stdin: Option<String>,
+        /// The content of the entry
+        #[clap(long, short, conflicts_with = "file")]
+        message: Option<String>,
+        /// A file to read the content of the entry from
+        #[clap(long, short)]
+        file: Option<PathBuf>,
+        /// The name of the template to start the entry from, instead of the
+        /// one in config
+        #[clap(long, short, conflicts_with_all = &["stdin", "message", "file"])]
+        template: Option<String>,
+        /// Start with a blank entry, even if config specifies a template
+        #[clap(long, conflicts_with = "template")]
+        no_template: bool,
+    },
     /// List all existing journal entries
-    List,
+    List {
+        /// The time zone to display dates and times in, instead of the one in
+        /// config
+        #[clap(long, value_enum)]
+        timezone: Option<TimeZone>,
+    },
     /// Show one or all journal entries
     Show {
         /// The ID of the entry to show
@@ -27,38 +109,321 @@ pub enum Action {
         /// Whether to print the entry in TOML format instead of the default
         #[clap(long, short)]
         toml: bool,
+        /// The time zone to display dates and times in, instead of the one in
+        /// config
+        #[clap(long, value_enum)]
+        timezone: Option<TimeZone>,
     },
     /// Edit an existing journal entry
     Edit {
         /// The ID of the entry to edit
         id: Uuid,
     },
+    /// Recover entries that were being written when the editor or jarida
+    /// failed
+    Drafts {
+        #[clap(subcommand)]
+        action: DraftAction,
+    },
+    /// Change the password or what is needed to unlock the journal
+    ///
+    /// Without options, the password is changed. The config file is updated to
+    /// match.
+    Passwd {
+        /// Require the keyfile to unlock the journal, in addition to the
+        /// password. If the file does not exist, it is created with random
+        /// contents.
+        #[clap(long, value_name = "FILE", conflicts_with = "remove-keyfile")]
+        add_keyfile: Option<PathBuf>,
+        /// Only require the keyfile given with --add-keyfile, without a
+        /// password
+        #[clap(long, requires = "add-keyfile")]
+        keyfile_only: bool,
+        /// Stop using the keyfile. Only the password is required afterwards.
+        #[clap(long)]
+        remove_keyfile: bool,
+    },
+    /// Manage who can unlock the journal
+    ///
+    /// Each user unlocks the journal with their own name and credentials.
+    Access {
+        #[clap(subcommand)]
+        action: Option<AccessAction>,
+    },
+    /// Manage the recovery key, which can unlock the journal if a password or
+    /// keyfile is lost
+    RecoveryKey {
+        #[clap(subcommand)]
+        action: RecoveryKeyAction,
+    },
+    /// Encrypt all journal data again, optionally switching to another cipher
+    ///
+    /// This gives no protection if the journal's key has been compromised.
+    /// Entries and drafts are encrypted with a new generation of keys, but
+    /// those keys are derived from the journal's key, which stays the same, so
+    /// anyone who has it can still decrypt everything. It also means everyone
+    /// who can unlock the journal still can afterwards. If this is
+    /// interrupted, run it again.
+    Reencrypt {
+        /// The cipher to encrypt data with from now on, instead of the one in
+        /// use. ChaCha20-Poly1305 is faster on CPUs without AES instructions.
+        #[clap(long, possible_values = ["aes-256-gcm", "chacha20-poly1305"])]
+        cipher: Option<Cipher>,
+    },
     /// Index all journal entries
     ///
     /// This should only be needed for maintenance reasons.
     Index,
-    /// Initialize the system
-    Init {
-        /// The directory to use for program data. If omitted, a directory will be created in the user's home directory.
+    /// Upgrade the journal data to the format used by this version
+    ///
+    /// A backup of the journal data is made before anything is changed.
+    Upgrade,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum ConfigAction {
+    /// Show every setting, its value and where the value came from
+    List,
+    /// Show the value of a setting
+    Get {
+        /// The setting's key, e.g. `display_timezone`
+        key: String,
+    },
+    /// Change a setting in the config file
+    Set {
+        /// The setting's key, e.g. `display_timezone`
+        key: String,
+        /// The new value
+        value: String,
+    },
+    /// Open the config file in the editor. It is only saved if it is valid.
+    Edit,
+    /// Check that the config file is valid
+    Validate,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum JournalAction {
+    /// List all journals in the registry
+    List,
+    /// Add a journal to the registry, or change where an existing one is
+    Add {
+        /// The name of the journal
+        name: String,
+        /// The journal's directory, or the directory containing it. If
+        /// omitted, the journal currently in use is added.
         dir: Option<PathBuf>,
     },
+    /// Remove a journal from the registry. Its data is left untouched.
+    Remove {
+        /// The name of the journal
+        name: String,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum AccessAction {
+    /// List the users who can unlock the journal
+    List,
+    /// Let another user unlock the journal
+    Add {
+        /// The user's name
+        user: String,
+        /// Require the keyfile to unlock the journal, in addition to the
+        /// password. If the file does not exist, it is created with random
+        /// contents.
+        #[clap(long, value_name = "FILE")]
+        keyfile: Option<PathBuf>,
+        /// Only require the keyfile, without a password
+        #[clap(long, requires = "keyfile")]
+        keyfile_only: bool,
+    },
+    /// Stop a user from unlocking the journal
+    Remove {
+        /// The user's name
+        user: String,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum RecoveryKeyAction {
+    /// Create a recovery key, replacing the previous one
+    ///
+    /// The key is only shown once.
+    Create {
+        /// Split the recovery key into this many shares instead of showing it
+        #[clap(long, value_name = "N", requires = "threshold")]
+        shares: Option<u8>,
+        /// The number of shares needed to rebuild the recovery key
+        #[clap(long, value_name = "K", requires = "shares")]
+        threshold: Option<u8>,
+        /// Write each share to a file in this directory instead of showing
+        /// them
+        #[clap(long, value_name = "DIR", requires = "shares")]
+        out_dir: Option<PathBuf>,
+    },
+    /// Remove the recovery key
+    Remove,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum DraftAction {
+    /// List all drafts
+    List,
+    /// Continue editing a draft and save it
+    Resume {
+        /// The ID of the draft to resume
+        id: Uuid,
+    },
+    /// Permanently delete a draft
+    Discard {
+        /// The ID of the draft to delete
+        id: Uuid,
+    },
 }
 
 impl Args {
-    pub fn run(&self, cfg: &Config, db: &mut GuardedStore) -> anyhow::Result<()> {
-        match &self.action {
-            Action::New => new_entry(cfg, db),
-            Action::List => print_entry_list(db),
-            Action::Show { id, toml } => {
+    pub fn run(&self) -> anyhow::Result<()> {
+        // Handle the commands that do not work with unlocked journal data.
+        let action = match &self.action {
+            Action::Store(action) => action,
+            Action::Init { dir, name, cipher } => {
+                return init(&self.overrides, dir.clone(), name.clone(), *cipher)
+            }
+            Action::MigrateXdg => return migrate_to_xdg(),
+            Action::Journals { action } => {
+                return match action {
+                    None | Some(JournalAction::List) => print_journal_list(&self.overrides),
+                    Some(JournalAction::Add { name, dir }) => add_journal(name, dir.clone()),
+                    Some(JournalAction::Remove { name }) => remove_journal(name),
+                }
+            }
+            Action::Config { action } => {
+                return match action {
+                    ConfigAction::List => print_config_list(&self.overrides),
+                    ConfigAction::Get { key } => print_config_value(&self.overrides, key),
+                    ConfigAction::Set { key, value } => {
+                        set_config_value(&self.overrides, key, value)
+                    }
+                    ConfigAction::Edit => edit_config(&self.overrides),
+                    ConfigAction::Validate => validate_config(&self.overrides),
+                }
+            }
+            // Recovering is for when the usual credentials are lost.
+            Action::Recover { shares } => {
+                let cfg = Config::find(&self.overrides)?;
+                let mut db = Store::open(cfg.data_store_path()?)?;
+                db.check_version()?;
+                return recover(&cfg, &mut db, shares.as_deref());
+            }
+        };
+
+        let cfg = Config::find(&self.overrides)?;
+        let mut db = Store::open(cfg.data_store_path()?)?;
+        // Only upgrading can work with journal data in an older format.
+        if !matches!(action, StoreAction::Upgrade) {
+            db.check_version()?;
+        }
+        let (username, mut data_guard) = get_and_validate_credentials(&cfg, &mut db)?;
+        data_guard.set_padding(cfg.padding());
+        data_guard.set_suite(db.get_suite()?);
+        let mut db = db.guard(&mut data_guard, &username);
+        // The index can only be sealed once the store has been upgraded.
+        if !matches!(action, StoreAction::Upgrade) {
+            db.set_hide_activity(cfg.hide_activity())?;
+        }
+        let result = action.run(&cfg, &mut db);
+        // Reading entries changes their access times too, so hide the times
+        // even if the command failed.
+        if cfg.hide_activity() {
+            return result.and(db.store.hide_file_times());
+        }
+        result
+    }
+}
+
+impl StoreAction {
+    fn run(&self, cfg: &Config, db: &mut GuardedStore) -> anyhow::Result<()> {
+        if !matches!(self, StoreAction::Drafts { .. }) {
+            notify_about_drafts(db)?;
+        }
+        match self {
+            StoreAction::New {
+                stdin,
+                message,
+                file,
+                template,
+                no_template,
+            } => {
+                let source = if stdin.is_some() {
+                    EntrySource::Stdin
+                } else if let Some(message) = message {
+                    EntrySource::Message(message.clone())
+                } else if let Some(file) = file {
+                    EntrySource::File(file.clone())
+                } else if *no_template {
+                    EntrySource::Editor { template: None }
+                } else {
+                    EntrySource::Editor {
+                        template: template.clone().or_else(|| cfg.template.clone()),
+                    }
+                };
+                new_entry(cfg, db, source)
+            }
+            StoreAction::List { timezone } => {
+                print_entry_list(cfg, db, cfg.display_timezone(*timezone))
+            }
+            StoreAction::Show { id, toml, timezone } => {
+                let format = if *toml { Format::Toml } else { Format::Default };
+                let zone = cfg.display_timezone(*timezone);
                 if let Some(id) = id {
-                    print_entry(db, *id, if *toml { Format::Toml } else { Format::Default })
+                    print_entry(cfg, db, *id, format, zone)
                 } else {
-                    print_all_entries(db, if *toml { Format::Toml } else { Format::Default })
+                    print_all_entries(cfg, db, format, zone)
                 }
             }
-            Action::Edit { id } => edit_entry(cfg, db, *id),
-            Action::Index => db.index(),
-            Action::Init { dir } => init(dir.clone()),
+            StoreAction::Edit { id } => edit_entry(cfg, db, *id),
+            StoreAction::Drafts { action } => match action {
+                DraftAction::List => print_draft_list(cfg, db),
+                DraftAction::Resume { id } => resume_draft(cfg, db, *id),
+                DraftAction::Discard { id } => discard_draft(db, *id),
+            },
+            StoreAction::Passwd {
+                add_keyfile,
+                keyfile_only,
+                remove_keyfile,
+            } => change_credentials(cfg, db, add_keyfile.clone(), *keyfile_only, *remove_keyfile),
+            StoreAction::Access { action } => match action {
+                None | Some(AccessAction::List) => print_access_list(cfg, db),
+                Some(AccessAction::Add {
+                    user,
+                    keyfile,
+                    keyfile_only,
+                }) => grant_access(db, user, keyfile.clone(), *keyfile_only),
+                Some(AccessAction::Remove { user }) => revoke_access(db, user),
+            },
+            StoreAction::RecoveryKey { action } => match action {
+                RecoveryKeyAction::Create {
+                    shares,
+                    threshold,
+                    out_dir,
+                } => create_recovery_key(db, shares.zip(*threshold), out_dir.clone()),
+                RecoveryKeyAction::Remove => remove_recovery_key(db),
+            },
+            StoreAction::Reencrypt { cipher } => reencrypt(db, *cipher),
+            StoreAction::Index => db.index(),
+            StoreAction::Upgrade => upgrade(db),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn verify_args() {
+        use clap::CommandFactory as _;
+        Args::command().debug_assert();
+    }
+}
@@ -1,11 +1,62 @@
 use anyhow::Context as _;
+use std::path::Path;
+use zeroize::Zeroizing;
 
 use super::{
     config::Config,
-    db::Store,
-    security::{CredentialGuard, DataGuard},
+    db::{KeySlot, Store},
+    security::{credential_secret, CredentialGuard, DataGuard, UnspecifiedError, SYSTEM_RNG},
 };
 
+/// Copy a directory and everything in it to a new directory.
+pub fn copy_dir_all(from: &Path, to: &Path) -> anyhow::Result<()> {
+    fs_err::create_dir(to)?;
+    for entry in fs_err::read_dir(from)? {
+        let entry = entry?;
+        let to = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &to)?;
+        } else {
+            fs_err::copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+/// Move a file or directory. If it can't be renamed, e.g. because it is moved
+/// to another file system, it is copied and then removed instead.
+pub fn move_path(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if to.exists() {
+        anyhow::bail!("{} already exists", to.display());
+    }
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    let is_dir = fs_err::symlink_metadata(from)?.is_dir();
+    let copied = if is_dir {
+        copy_dir_all(from, to)
+    } else {
+        fs_err::copy(from, to)
+            .map(|_| ())
+            .map_err(anyhow::Error::from)
+    };
+    if let Err(error) = copied {
+        // Don't leave a partial copy behind.
+        if is_dir {
+            let _ = std::fs::remove_dir_all(to);
+        } else {
+            let _ = std::fs::remove_file(to);
+        }
+        return Err(error);
+    }
+    if is_dir {
+        fs_err::remove_dir_all(from)?;
+    } else {
+        fs_err::remove_file(from)?;
+    }
+    Ok(())
+}
+
 /// The formats for printing out entries
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Format {
@@ -13,10 +64,75 @@ pub enum Format {
     Toml,
 }
 
-/// The formatting string for all date-time (Sun  8-Jul-2001 00:34)
-pub const DATETIME_FORMAT: &[::time::format_description::FormatItem<'_>] = time::macros::format_description!(
-    "[weekday repr:short] [day padding:space]-[month repr:short]-[year] [hour repr:24]:[minute]"
-);
+/// The default formatting string for all date-times (Sun  8-Jul-2001 00:34)
+pub const DEFAULT_DATETIME_FORMAT: &str =
+    "[weekday repr:short] [day padding:space]-[month repr:short]-[year] [hour repr:24]:[minute]";
+/// The default formatting string for all dates (2001-07-08)
+pub const DEFAULT_DATE_FORMAT: &str = "[year]-[month]-[day]";
+/// The name of the ISO 8601 format, which can be used in place of a formatting
+/// string.
+pub const ISO8601: &str = "iso8601";
+/// The formatting string for ISO 8601 date-times (2001-07-08T00:34:00+02:00)
+pub const ISO8601_DATETIME_FORMAT: &str =
+    "[year]-[month]-[day]T[hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]";
+/// The formatting string for ISO 8601 dates (2001-07-08)
+pub const ISO8601_DATE_FORMAT: &str = "[year]-[month]-[day]";
+
+/// Make sure the formatting string is valid.
+pub fn validate_datetime_format(format: &str) -> anyhow::Result<()> {
+    if format != ISO8601 {
+        time::format_description::parse(format)
+            .context(format!("Invalid date/time format: {}", format))?;
+    }
+    Ok(())
+}
+
+/// The time zones date-times can be displayed in
+#[derive(
+    Debug, Default, PartialEq, Copy, Clone, serde::Serialize, serde::Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeZone {
+    /// The time zone the entry was written in
+    Written,
+    /// The reader's local time zone
+    #[default]
+    Local,
+    /// Coordinated Universal Time
+    Utc,
+}
+
+/// Format the date-time for display in the specified time zone using the
+/// specified formatting string.
+///
+/// The default format does not include the offset from UTC, so it is appended
+/// unless the date-time is displayed in the local time zone.
+pub fn format_datetime(
+    datetime: time::OffsetDateTime,
+    zone: TimeZone,
+    format: &str,
+) -> anyhow::Result<String> {
+    let description = time::format_description::parse(format)
+        .context(format!("Invalid date/time format: {}", format))?;
+    let offset_format =
+        time::macros::format_description!("[offset_hour sign:mandatory]:[offset_minute]");
+    let datetime = match zone {
+        TimeZone::Written => datetime,
+        TimeZone::Local => datetime.to_offset(time::UtcOffset::current_local_offset()?),
+        TimeZone::Utc => datetime.to_offset(time::UtcOffset::UTC),
+    };
+    let mut formatted = datetime.format(&description)?;
+    if format == DEFAULT_DATETIME_FORMAT {
+        match zone {
+            TimeZone::Written => {
+                formatted = format!("{} {}", formatted, datetime.format(offset_format)?)
+            }
+            TimeZone::Local => (),
+            TimeZone::Utc => formatted.push_str(" UTC"),
+        }
+    }
+    Ok(formatted)
+}
 
 /// Retry the specified function up to the specified number of times times until is succeeds.
 fn retry<T, S: FnMut() -> anyhow::Result<T>>(max: usize, mut func: S) -> anyhow::Result<T> {
@@ -35,17 +151,22 @@ fn retry<T, S: FnMut() -> anyhow::Result<T>>(max: usize, mut func: S) -> anyhow:
     result
 }
 
+/// A password, which is wiped from memory when dropped
+pub type Password = Zeroizing<String>;
+
 /// Prompt the user for a password once
-fn prompt_password() -> anyhow::Result<String> {
-    rpassword::prompt_password_stdout("Password: ").context("Error getting a password")
+fn prompt_password() -> anyhow::Result<Password> {
+    rpassword::prompt_password_stdout("Password: ")
+        .map(Zeroizing::new)
+        .context("Error getting a password")
 }
 
 /// Prompt the user for a password and prompt again to confirm it. If the
 /// passwords do not match, prompt up to 3 more times before failing.
-fn prompt_and_confirm_password() -> anyhow::Result<String> {
+fn prompt_and_confirm_password() -> anyhow::Result<Password> {
     let err = "Error getting a password";
-    let p1 = rpassword::prompt_password_stdout("Password: ").context(err)?;
-    let p2 = rpassword::prompt_password_stdout("Confirm: ").context(err)?;
+    let p1 = Zeroizing::new(rpassword::prompt_password_stdout("Password: ").context(err)?);
+    let p2 = Zeroizing::new(rpassword::prompt_password_stdout("Confirm: ").context(err)?);
     if p1 == p2 {
         Ok(p1)
     } else {
@@ -54,7 +175,7 @@ fn prompt_and_confirm_password() -> anyhow::Result<String> {
 }
 
 // Prompt the use for their name once.
-fn prompt_username() -> anyhow::Result<String> {
+pub fn prompt_username() -> anyhow::Result<String> {
     use std::io::BufRead as _;
     use std::io::Write as _;
 
@@ -69,25 +190,197 @@ fn prompt_username() -> anyhow::Result<String> {
     Ok(username)
 }
 
+/// The environment variable naming a file descriptor to read the password from
+pub const PASSWORD_FD_VAR: &str = "JARIDA_PASSWORD_FD";
+
+/// Get the first line of the text, without the line ending.
+fn first_line(text: &str) -> Password {
+    Zeroizing::new(text.lines().next().unwrap_or_default().to_string())
+}
+
+/// Read the password from the file descriptor in the JARIDA_PASSWORD_FD
+/// environment variable, if it is set. Only the first line is used.
+fn read_password_from_fd() -> anyhow::Result<Option<Password>> {
+    let fd = match std::env::var(PASSWORD_FD_VAR) {
+        Ok(fd) if !fd.is_empty() => fd,
+        Ok(_) | Err(std::env::VarError::NotPresent) => return Ok(None),
+        Err(e) => return Err(e).context(format!("Invalid {}", PASSWORD_FD_VAR)),
+    };
+    #[cfg(unix)]
+    {
+        use std::io::Read as _;
+        use std::os::unix::io::FromRawFd as _;
+
+        let fd: std::os::unix::io::RawFd = fd
+            .parse()
+            .context(format!("{} must be a file descriptor", PASSWORD_FD_VAR))?;
+        if fd <= 2 {
+            anyhow::bail!("{} must not be stdin, stdout or stderr", PASSWORD_FD_VAR);
+        }
+        // SAFETY: The file descriptor is handed to us to read the password
+        // from and is not used anywhere else. It is closed once read.
+        let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+        let mut password = Zeroizing::new(String::new());
+        file.read_to_string(&mut password)
+            .context(format!("Could not read the password from fd {}", fd))?;
+        Ok(Some(first_line(&password)))
+    }
+    #[cfg(not(unix))]
+    {
+        anyhow::bail!("{} is only supported on Unix", PASSWORD_FD_VAR)
+    }
+}
+
+/// Run the password command and get the first line of its output.
+fn run_password_command(command: &str) -> anyhow::Result<Password> {
+    let words =
+        shell_words::split(command).context(format!("Invalid password command: {}", command))?;
+    let (program, args) = words
+        .split_first()
+        .context("The password command is empty")?;
+    let output = std::process::Command::new(program)
+        .args(args)
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .context(format!("Could not run password command: {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!("The password command failed with {}", output.status);
+    }
+    let output = Zeroizing::new(
+        String::from_utf8(output.stdout).context("The password command printed invalid UTF-8")?,
+    );
+    Ok(first_line(&output))
+}
+
+/// Read the first line of the password file.
+fn read_password_file(path: &Path) -> anyhow::Result<Password> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let mode = std::fs::metadata(path)
+            .context(format!("Could not read password file {}", path.display()))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            eprintln!(
+                "Warning: {} can be read by other users. Run `chmod 600` on it.",
+                path.display()
+            );
+        }
+    }
+    let password = Zeroizing::new(
+        std::fs::read_to_string(path)
+            .context(format!("Could not read password file {}", path.display()))?,
+    );
+    Ok(first_line(&password))
+}
+
+/// Get the password from the password command, the password file or the
+/// password in config, whichever is given.
+fn get_password_from_config(cfg: &Config) -> anyhow::Result<Option<Password>> {
+    if let Some(ref command) = cfg.password_command {
+        return run_password_command(command).map(Some);
+    }
+    if let Some(ref path) = cfg.password_file {
+        return read_password_file(path).map(Some);
+    }
+    if cfg.password.is_some() {
+        eprintln!(
+            "Warning: Your password is stored in plaintext in config. Anyone who can read {} can read your journal. Use password_command or password_file instead.",
+            cfg.path
+                .as_deref()
+                .unwrap_or_else(|| Path::new(Config::FILE_NAME))
+                .display()
+        );
+    }
+    Ok(cfg.password.clone())
+}
+
+/// Ask the user a yes/no question. Anything but "y" or "yes" is taken to
+/// mean no.
+pub fn prompt_yes_no(question: &str) -> anyhow::Result<bool> {
+    use std::io::BufRead as _;
+    use std::io::Write as _;
+
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let answer = std::io::stdin()
+        .lock()
+        .lines()
+        .next()
+        .unwrap_or_else(|| Ok(String::new()))?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// The placeholder in the editor command that is replaced by the file to edit.
+const EDITOR_FILE_PLACEHOLDER: &str = "{file}";
+
+/// Get the command line for the user's editor of choice. The editor in config
+/// is preferred, followed by the `VISUAL` and `EDITOR` environment variables.
+///
+/// Returns an error if no editor is available.
+fn get_editor(cfg: &Config) -> anyhow::Result<String> {
+    cfg.editor
+        .clone()
+        .into_iter()
+        .chain(std::env::var("VISUAL").ok())
+        .chain(std::env::var("EDITOR").ok())
+        .find(|editor| !editor.trim().is_empty())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "No editor available. Set `editor` in config or the VISUAL or EDITOR environment variable."
+            )
+        })
+}
+
+/// Build the command to open the specified file with the editor command line.
+/// Every occurrence of `{file}` in the command line is replaced with the path
+/// to the file. If there are none, the path is appended as the last argument.
+fn build_editor_command(editor: &str, path: &Path) -> anyhow::Result<std::process::Command> {
+    let mut words = shell_words::split(editor)
+        .context(format!("Invalid editor command: {}", editor))?
+        .into_iter();
+    let program = words
+        .next()
+        .context(format!("Invalid editor command: {:?}", editor))?;
+    let mut command = std::process::Command::new(program);
+    let mut has_placeholder = false;
+    for word in words {
+        if word == EDITOR_FILE_PLACEHOLDER {
+            has_placeholder = true;
+            command.arg(path);
+        } else if word.contains(EDITOR_FILE_PLACEHOLDER) {
+            has_placeholder = true;
+            let file = path
+                .to_str()
+                .context(format!("{} is not valid UTF-8", path.display()))?;
+            command.arg(word.replace(EDITOR_FILE_PLACEHOLDER, file));
+        } else {
+            command.arg(word);
+        }
+    }
+    if !has_placeholder {
+        command.arg(path);
+    }
+    Ok(command)
+}
+
 // Open the specified file with the editor defined in config
-pub fn open_file_in_editor<P: AsRef<std::path::Path>>(cfg: &Config, path: P) -> anyhow::Result<()> {
+pub fn open_file_in_editor<P: AsRef<Path>>(cfg: &Config, path: P) -> anyhow::Result<()> {
     let path = path.as_ref();
-    log::trace!("Opening {} in {}", path.display(), cfg.editor.display());
+    let editor = get_editor(cfg)?;
+    log::trace!("Opening {} in {}", path.display(), editor);
 
-    let status = std::process::Command::new(cfg.editor.as_os_str())
-        .arg(path)
+    let status = build_editor_command(&editor, path)?
         .status()
-        .context(format!(
-            "Failed to execute {} {}",
-            cfg.editor.display(),
-            path.display()
-        ))?;
+        .context(format!("Failed to execute {} {}", editor, path.display()))?;
     if status.success() {
         Ok(())
     } else {
         Err(anyhow::anyhow!(
             "{} exited with code {}",
-            cfg.editor.display(),
+            editor,
             status
                 .code()
                 .map(|i| format!("{}", i))
@@ -110,17 +403,44 @@ pub fn get_and_validate_credentials(
 
     // Get encryption data from the database.
     let salt = db.get_salt()?;
-    let mut encrypted_key = db.get_key()?.unwrap_or_default();
+    let slots = db.get_key_slots()?;
 
     // Get and confirm the user's name and password
 
+    let mode = cfg.unlock_mode();
+    let keyfile = if mode.uses_keyfile() {
+        let path = cfg
+            .keyfile
+            .as_ref()
+            .context("No keyfile is specified in config")?;
+        Some(read_keyfile(path)?)
+    } else {
+        None
+    };
     let mut username = cfg.user.clone().ok_or(()).or_else(|_| prompt_username())?;
-    let mut password = cfg.password.clone();
+    if !mode.uses_password() {
+        return unlock_with_keyfile(
+            db,
+            salt,
+            slots,
+            username,
+            keyfile.as_deref().map(Vec::as_slice),
+        );
+    }
+    let password_from_fd = read_password_from_fd()?;
+    let from_fd = password_from_fd.is_some();
+    let mut password = match password_from_fd {
+        Some(password) => Some(password),
+        None => get_password_from_config(cfg)?,
+    };
 
-    if encrypted_key.is_empty() {
+    if slots.is_empty() {
         // The database has no key, which means the user has never put anything
         // in the database.
-        if let Some(password) = &password {
+        if from_fd {
+            // The password was handed over by another program, which has no
+            // way of confirming it.
+        } else if let Some(password) = &password {
             // The user has specified a password in config, confirm it before
             // blindly using it to encrypt the key for the database.
             println!("Please confirm your password");
@@ -143,24 +463,23 @@ pub fn get_and_validate_credentials(
     // In that case we should also prompt the user for the password here.
     let mut password = password.ok_or(()).or_else(|_| prompt_password())?;
     let mut cred_guard = CredentialGuard::new(
-        salt.try_into().expect("Salt is the wrong size"),
+        salt.try_into().ok().context("Salt is the wrong size")?,
         &username,
-        &password,
+        &credential_secret(Some(&password), keyfile.as_deref().map(Vec::as_slice)),
     );
 
-    if encrypted_key.is_empty() {
+    let slots = if slots.is_empty() {
         // We have the user's credentials so we can generate an encrypted key
         // for the database.
-        encrypted_key = cred_guard
-            .generate_encrypted_key()
-            .map_err(|_| anyhow::anyhow!("Could not generate database key"))?;
-        db.update_key(&encrypted_key)?;
-    }
+        create_key_slot(db, &cred_guard, &username)?
+    } else {
+        slots
+    };
 
     // Validate the credentials. Give the user 3 tries.
     let mut data_guard = None;
     for i in 0..3 {
-        match cred_guard.try_decrypt_key(encrypted_key.clone()) {
+        match try_unlock(cred_guard, &slots, &username) {
             Ok(guard) => {
                 data_guard = Some(guard);
                 break;
@@ -171,10 +490,169 @@ pub fn get_and_validate_credentials(
                     println!("Invalid credentials. Try again.");
                     username = prompt_username()?;
                     password = prompt_password()?;
-                    cred_guard.update_credentials(&username, &password);
+                    cred_guard.update_credentials(
+                        &username,
+                        &credential_secret(Some(&password), keyfile.as_deref().map(Vec::as_slice)),
+                    );
                 }
             }
         }
     }
     Ok((username, data_guard.context("Invalid credentials")?))
 }
+
+/// Read the keyfile at the specified path.
+pub fn read_keyfile(path: &Path) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+    let keyfile = Zeroizing::new(std::fs::read(path).context(format!(
+        "Could not read keyfile {}. Is the drive it is on mounted?",
+        path.display()
+    ))?);
+    if keyfile.is_empty() {
+        anyhow::bail!("Keyfile {} is empty", path.display());
+    }
+    Ok(keyfile)
+}
+
+/// Create a new keyfile with random contents, only readable by the user.
+pub fn create_keyfile(path: &Path) -> anyhow::Result<()> {
+    use ring::rand::SecureRandom as _;
+    use std::io::Write as _;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+    let mut contents = Zeroizing::new([0u8; 64]);
+    SYSTEM_RNG
+        .fill(&mut *contents)
+        .map_err(UnspecifiedError::from)?;
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(&*contents))
+        .context(format!("Could not create keyfile {}", path.display()))?;
+    Ok(())
+}
+
+/// Generate the key for a new database and put it in a key slot for the
+/// user. Returns the database's key slots.
+fn create_key_slot(
+    db: &mut Store,
+    cred_guard: &CredentialGuard,
+    username: &str,
+) -> anyhow::Result<Vec<KeySlot>> {
+    let encrypted_key = cred_guard
+        .generate_encrypted_key()
+        .map_err(|_| anyhow::anyhow!("Could not generate database key"))?;
+    let slots = vec![KeySlot::new(username, encrypted_key)];
+    db.set_key_slots(&slots)?;
+    Ok(slots)
+}
+
+/// Try to decrypt the database's key from each of the user's key slots.
+fn try_unlock(
+    mut cred_guard: CredentialGuard,
+    slots: &[KeySlot],
+    username: &str,
+) -> Result<DataGuard, CredentialGuard> {
+    for slot in slots.iter().filter(|slot| slot.is_for(username)) {
+        cred_guard = match cred_guard.try_decrypt_key(slot.key.clone()) {
+            Ok(guard) => return Ok(guard),
+            Err(cred_guard) => cred_guard,
+        };
+    }
+    Err(cred_guard)
+}
+
+/// Unlock a journal that only needs the keyfile.
+fn unlock_with_keyfile(
+    db: &mut Store,
+    salt: Vec<u8>,
+    slots: Vec<KeySlot>,
+    mut username: String,
+    keyfile: Option<&[u8]>,
+) -> anyhow::Result<(String, DataGuard)> {
+    use std::convert::TryInto as _;
+
+    let secret = credential_secret(None, keyfile);
+    let mut cred_guard = CredentialGuard::new(
+        salt.try_into().ok().context("Salt is the wrong size")?,
+        &username,
+        &secret,
+    );
+    let slots = if slots.is_empty() {
+        create_key_slot(db, &cred_guard, &username)?
+    } else {
+        slots
+    };
+
+    // Only the user's name can be wrong, so give the user 3 tries at it.
+    for i in 0..3 {
+        match try_unlock(cred_guard, &slots, &username) {
+            Ok(guard) => return Ok((username, guard)),
+            Err(g) => {
+                cred_guard = g;
+                if i != 2 {
+                    println!("Invalid user name or keyfile. Try again.");
+                    username = prompt_username()?;
+                    cred_guard.update_credentials(&username, &secret);
+                }
+            }
+        }
+    }
+    Err(anyhow::anyhow!("Invalid credentials"))
+}
+
+/// Prompt the user for a new password and confirm it.
+pub fn prompt_new_password() -> anyhow::Result<Password> {
+    println!("Please enter the new password");
+    retry(3, prompt_and_confirm_password)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(command: &std::process::Command) -> Vec<&std::ffi::OsStr> {
+        command.get_args().collect()
+    }
+
+    #[test]
+    fn editor_command_appends_file() {
+        let command = build_editor_command("code --wait", Path::new("/tmp/entry")).unwrap();
+        assert_eq!(command.get_program(), "code");
+        assert_eq!(args(&command), ["--wait", "/tmp/entry"]);
+    }
+
+    #[test]
+    fn editor_command_replaces_placeholder() {
+        let command =
+            build_editor_command("'my editor' -c '+e {file}' {file}", Path::new("/tmp/entry"))
+                .unwrap();
+        assert_eq!(command.get_program(), "my editor");
+        assert_eq!(args(&command), ["-c", "+e /tmp/entry", "/tmp/entry"]);
+    }
+
+    #[test]
+    fn datetime_formats_are_validated() {
+        assert!(validate_datetime_format(DEFAULT_DATETIME_FORMAT).is_ok());
+        assert!(validate_datetime_format(ISO8601).is_ok());
+        assert!(validate_datetime_format(ISO8601_DATETIME_FORMAT).is_ok());
+        assert!(validate_datetime_format("[year]-[mnth]").is_err());
+    }
+
+    #[test]
+    fn password_command_uses_first_line() {
+        let password = run_password_command("printf 'secret word\\nmore'").unwrap();
+        assert_eq!(*password, "secret word");
+        assert!(run_password_command("false").is_err());
+    }
+
+    #[test]
+    fn editor_command_rejects_invalid() {
+        assert!(build_editor_command("", Path::new("/tmp/entry")).is_err());
+        assert!(build_editor_command("vim 'unterminated", Path::new("/tmp/entry")).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{
     fs::{self, File},
     io::Read,
@@ -6,29 +6,384 @@ use std::{
 
 use anyhow::Context as _;
 
-#[derive(Debug, serde::Deserialize)]
+use crate::common::{
+    validate_datetime_format, Password, TimeZone, DEFAULT_DATETIME_FORMAT, DEFAULT_DATE_FORMAT,
+    ISO8601, ISO8601_DATETIME_FORMAT, ISO8601_DATE_FORMAT,
+};
+use crate::registry::Registry;
+use crate::security::Padding;
+
+/// Options, given on the command line or in the environment, that take
+/// priority over finding the config file and over the values in it
+#[derive(Debug, Default, Clone, clap::Args)]
+pub struct ConfigOverrides {
+    /// The config file to use, instead of searching for one
+    #[clap(long, global = true, env = "JARIDA_CONFIG", value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// The name of the journal to use, from the journal registry
+    #[clap(long, short, global = true, env = "JARIDA_JOURNAL")]
+    pub journal: Option<String>,
+    /// The directory to save all journal data in, instead of the one in config
+    #[clap(long, global = true, env = "JARIDA_DIR", value_name = "DIR")]
+    pub journal_dir: Option<PathBuf>,
+    /// The editor command to use, instead of the one in config
+    #[clap(long, global = true, value_name = "COMMAND")]
+    pub editor: Option<String>,
+}
+
+/// How the configuration directory was found
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ConfigSource {
+    /// Given explicitly with --config or JARIDA_CONFIG
+    Override,
+    /// Selected by name from the journal registry
+    Registry(String),
+    /// Found in a parent of the current directory
+    Parent,
+    /// Found in the user's config or home directory
+    #[default]
+    User,
+    /// No config file was found, so the defaults are used
+    None,
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigSource::Override => write!(f, "given with --config or JARIDA_CONFIG"),
+            ConfigSource::Registry(name) => write!(
+                f,
+                "journal {:?} selected with --journal or JARIDA_JOURNAL",
+                name
+            ),
+            ConfigSource::Parent => write!(f, "found in a parent of the current directory"),
+            ConfigSource::User => write!(f, "found in the user's config directory"),
+            ConfigSource::None => write!(f, "no config file was found, using defaults"),
+        }
+    }
+}
+
+/// What is needed to unlock a journal
+#[derive(Debug, Default, PartialEq, Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum UnlockMode {
+    /// The user's name and password
+    #[default]
+    #[serde(rename = "password")]
+    Password,
+    /// The user's name and the keyfile
+    #[serde(rename = "keyfile")]
+    Keyfile,
+    /// The user's name, password and the keyfile
+    #[serde(rename = "password+keyfile")]
+    PasswordAndKeyfile,
+}
+
+impl UnlockMode {
+    /// Whether a password is needed to unlock the journal.
+    pub fn uses_password(self) -> bool {
+        self != UnlockMode::Keyfile
+    }
+
+    /// Whether the keyfile is needed to unlock the journal.
+    pub fn uses_keyfile(self) -> bool {
+        self != UnlockMode::Password
+    }
+}
+
+impl std::fmt::Display for UnlockMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UnlockMode::Password => write!(f, "password"),
+            UnlockMode::Keyfile => write!(f, "keyfile"),
+            UnlockMode::PasswordAndKeyfile => write!(f, "password+keyfile"),
+        }
+    }
+}
+
+/// The type of value a setting takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingKind {
+    String,
+    Path,
+    Bool,
+    /// One of the listed strings
+    OneOf(&'static [&'static str]),
+}
+
+/// A setting that can be specified in the config file
+#[derive(Debug)]
+pub struct Setting {
+    /// The setting's key in the config file
+    pub key: &'static str,
+    /// The type of value the setting takes
+    pub kind: SettingKind,
+    /// A short description of the setting
+    pub description: &'static str,
+}
+
+impl Setting {
+    /// Find the setting with the specified key. Dashes are treated like
+    /// underscores.
+    ///
+    /// Returns an error if there is no such setting.
+    pub fn find(key: &str) -> anyhow::Result<&'static Setting> {
+        let key = key.replace('-', "_");
+        SETTINGS
+            .iter()
+            .find(|setting| setting.key == key)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown setting {:?}. Run `jarida config list` to see them.",
+                    key
+                )
+            })
+    }
+
+    /// Parse a value for the setting, as given on the command line.
+    pub fn parse_value(&self, value: &str) -> anyhow::Result<toml::Value> {
+        match self.kind {
+            SettingKind::String | SettingKind::Path => Ok(toml::Value::String(value.to_string())),
+            SettingKind::Bool => Ok(toml::Value::Boolean(
+                value
+                    .parse()
+                    .context(format!("{} must be either true or false", self.key))?,
+            )),
+            SettingKind::OneOf(options) => {
+                if options.contains(&value) {
+                    Ok(toml::Value::String(value.to_string()))
+                } else {
+                    Err(anyhow::anyhow!(
+                        "{} must be one of: {}",
+                        self.key,
+                        options.join(", ")
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Every setting that can be specified in the config file
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "editor",
+        kind: SettingKind::String,
+        description: "The command line for your editor of choice",
+    },
+    Setting {
+        key: "user",
+        kind: SettingKind::String,
+        description: "Your name",
+    },
+    Setting {
+        key: "password",
+        kind: SettingKind::String,
+        description: "Your password",
+    },
+    Setting {
+        key: "password_command",
+        kind: SettingKind::String,
+        description: "The command line that prints your password",
+    },
+    Setting {
+        key: "password_file",
+        kind: SettingKind::Path,
+        description: "The file containing your password",
+    },
+    Setting {
+        key: "unlock",
+        kind: SettingKind::OneOf(&["password", "keyfile", "password+keyfile"]),
+        description: "What is needed to unlock the journal",
+    },
+    Setting {
+        key: "keyfile",
+        kind: SettingKind::Path,
+        description: "The keyfile used to unlock the journal",
+    },
+    Setting {
+        key: "padding",
+        kind: SettingKind::OneOf(&["none", "power-of-two", "4k", "64k"]),
+        description: "What size encrypted data is padded to, to hide its length",
+    },
+    Setting {
+        key: "hide_activity",
+        kind: SettingKind::Bool,
+        description: "Whether to hide when entries are written from anyone with the files",
+    },
+    Setting {
+        key: "template",
+        kind: SettingKind::String,
+        description: "The name of the template new entries start from",
+    },
+    Setting {
+        key: "display_timezone",
+        kind: SettingKind::OneOf(&["written", "local", "utc"]),
+        description: "The time zone dates and times are displayed in",
+    },
+    Setting {
+        key: "datetime_format",
+        kind: SettingKind::String,
+        description: "The format date-times are displayed in",
+    },
+    Setting {
+        key: "date_format",
+        kind: SettingKind::String,
+        description: "The format dates are displayed in",
+    },
+    Setting {
+        key: "temp_dir",
+        kind: SettingKind::Path,
+        description: "The temporary working directory",
+    },
+    Setting {
+        key: "secure_temp",
+        kind: SettingKind::Bool,
+        description: "Whether to keep working files in a RAM-backed directory",
+    },
+    Setting {
+        key: "journal_dir",
+        kind: SettingKind::Path,
+        description: "The directory to save all journal data in",
+    },
+];
+
+/// Where the value of a setting came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueOrigin {
+    /// The config file
+    File(PathBuf),
+    /// A command line option
+    Flag(&'static str),
+    /// An environment variable
+    Env(&'static str),
+    /// The setting was not specified
+    Default,
+}
+
+impl std::fmt::Display for ValueOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ValueOrigin::File(path) => write!(f, "{}", path.display()),
+            ValueOrigin::Flag(flag) => write!(f, "{} option", flag),
+            ValueOrigin::Env(name) => write!(f, "{} environment variable", name),
+            ValueOrigin::Default => write!(f, "default"),
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    /// The config file, if there is one
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    /// The directory containing the config file
+    #[serde(skip)]
+    pub dir: PathBuf,
+    /// How the config file was found
+    #[serde(skip)]
+    pub source: ConfigSource,
+    /// The settings given on the command line or in the environment
+    #[serde(skip)]
+    pub overrides: ConfigOverrides,
     /// The temporary work directory
+    #[serde(alias = "temp-dir", skip_serializing_if = "Option::is_none")]
     pub temp_dir: Option<PathBuf>,
+    /// Whether to keep temporary work files in a RAM-backed directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secure_temp: Option<bool>,
     /// The directory to save all journal data in
+    #[serde(alias = "journal-dir", skip_serializing_if = "Option::is_none")]
     pub journal_dir: Option<PathBuf>,
-    /// The path to the user's editor of choice
-    pub editor: PathBuf,
+    /// The command line for the user's editor of choice
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
     /// The name of the user
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
     /// Password
-    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<Password>,
+    /// The command line that prints the password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_command: Option<String>,
+    /// The file containing the password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<PathBuf>,
+    /// What is needed to unlock the journal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unlock: Option<UnlockMode>,
+    /// The keyfile used to unlock the journal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyfile: Option<PathBuf>,
+    /// How plaintext is padded before it is encrypted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding: Option<Padding>,
+    /// Whether to seal the index and hide file times
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hide_activity: Option<bool>,
+    /// The name of the template to start new entries from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// The time zone to display dates and times in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_timezone: Option<TimeZone>,
+    /// The format to display date-times in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datetime_format: Option<String>,
+    /// The format to display dates in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_format: Option<String>,
 }
 
 impl std::str::FromStr for Config {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Config, Self::Err> {
         let cfg = toml::from_str::<Config>(s).context("Invalid/malformed config")?;
+        if let Some(ref editor) = cfg.editor {
+            shell_words::split(editor).context(format!("Invalid editor command: {}", editor))?;
+        }
+        let password_sources = [
+            cfg.password.is_some(),
+            cfg.password_command.is_some(),
+            cfg.password_file.is_some(),
+        ];
+        if password_sources.iter().filter(|&&given| given).count() > 1 {
+            return Err(anyhow::anyhow!(
+                "Only one of password, password_command and password_file may be given"
+            ));
+        }
+        if let Some(ref command) = cfg.password_command {
+            shell_words::split(command)
+                .context(format!("Invalid password command: {}", command))?;
+        }
+        if let Some(ref password_file) = cfg.password_file {
+            if !password_file.is_absolute() {
+                return Err(anyhow::anyhow!("password_file must be an absolute path"));
+            }
+        }
+        if let Some(ref keyfile) = cfg.keyfile {
+            if !keyfile.is_absolute() {
+                return Err(anyhow::anyhow!("keyfile must be an absolute path"));
+            }
+        } else if cfg.unlock_mode().uses_keyfile() {
+            return Err(anyhow::anyhow!(
+                "keyfile must be given to unlock with {}",
+                cfg.unlock_mode()
+            ));
+        }
+        if let Some(ref format) = cfg.datetime_format {
+            validate_datetime_format(format).context("Invalid datetime_format")?;
+        }
+        if let Some(ref format) = cfg.date_format {
+            validate_datetime_format(format).context("Invalid date_format")?;
+        }
         if let Some(ref temp_dir) = cfg.temp_dir {
             if !temp_dir.is_absolute() {
                 return Err(anyhow::anyhow!("temp_dir must be an absolute path"));
             }
-        } else if let Some(ref journal_dir) = cfg.journal_dir {
+        }
+        if let Some(ref journal_dir) = cfg.journal_dir {
             if !journal_dir.is_absolute() {
                 return Err(anyhow::anyhow!("journal_dir must be an absolute path"));
             }
@@ -40,38 +395,101 @@ impl std::str::FromStr for Config {
 impl Config {
     /// The name of the configuration directory.
     pub const DIR_NAME: &'static str = ".jarida";
+    /// The name of the configuration directory in the XDG base directories.
+    pub const XDG_DIR_NAME: &'static str = "jarida";
     /// The name of the configuration file.
     pub const FILE_NAME: &'static str = "config.toml";
+    /// The name of the directory containing entry templates.
+    pub const TEMPLATES_DIR_NAME: &'static str = "templates";
+    /// The name of the file containing writing prompts.
+    pub const PROMPTS_FILE_NAME: &'static str = "prompts.txt";
 
-    /// Find the configuration file and parse it.
+    /// Find the configuration file and parse it, then apply the overrides.
+    ///
+    /// An explicitly given config file is preferred. Otherwise, if a journal
+    /// name is given, the journal's configuration is looked up in the journal
+    /// registry. Otherwise it is searched for in the parent directories and
+    /// then in the user's home directory. If there is no config file but the
+    /// journal directory is overridden, the defaults are used.
     ///
     /// Returns an error if the file cannot be found or is invalid/malformed.
-    pub fn find() -> anyhow::Result<Config> {
-        let path = Config::find_config_file_path()?;
-        let mut file =
-            File::open(&path).context(format!("Could open config {}", path.display()))?;
+    pub fn find(overrides: &ConfigOverrides) -> anyhow::Result<Config> {
+        let mut cfg = match Config::find_config_file_path(overrides) {
+            Ok((path, source)) => Config::load(&path, source)?,
+            Err(e) => match &overrides.journal_dir {
+                Some(dir) if overrides.config.is_none() && overrides.journal.is_none() => Config {
+                    dir: std::env::current_dir()?.join(dir),
+                    source: ConfigSource::None,
+                    ..Config::default()
+                },
+                _ => return Err(e),
+            },
+        };
+        if let Some(ref journal_dir) = overrides.journal_dir {
+            cfg.journal_dir = Some(std::env::current_dir()?.join(journal_dir));
+        }
+        if let Some(ref editor) = overrides.editor {
+            shell_words::split(editor).context(format!("Invalid editor command: {}", editor))?;
+            cfg.editor = Some(editor.clone());
+        }
+        cfg.overrides = overrides.clone();
+        Ok(cfg)
+    }
+
+    /// Parse the specified configuration file.
+    fn load(path: &Path, source: ConfigSource) -> anyhow::Result<Config> {
+        let mut file = File::open(path).context(format!("Could open config {}", path.display()))?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
-        contents
+        let mut cfg: Config = contents
             .parse()
-            .context(format!("Could not parse {}", path.display()))
+            .context(format!("Could not parse {}", path.display()))?;
+        cfg.dir = path
+            .parent()
+            .map(|dir| dir.to_path_buf())
+            .unwrap_or_default();
+        cfg.path = Some(path.to_path_buf());
+        cfg.source = source;
+        Ok(cfg)
     }
 
-    /// Try to find the config file, first in a parent directory, then in the
-    /// user's home directory. The file may not exist.
+    /// Try to find the config file. An explicitly given config file is
+    /// preferred, otherwise it is found in the config directory. The file may
+    /// not exist.
     ///
-    /// An error is returned if the configuration directory cannot be found.
-    pub fn find_config_file_path() -> anyhow::Result<PathBuf> {
-        let mut path = Config::find_config_dir_path()?;
+    /// Returns the file and how it was found.
+    pub fn find_config_file_path(
+        overrides: &ConfigOverrides,
+    ) -> anyhow::Result<(PathBuf, ConfigSource)> {
+        if let Some(ref path) = overrides.config {
+            return Ok((std::env::current_dir()?.join(path), ConfigSource::Override));
+        }
+        let (mut path, source) = Config::find_config_dir_path(overrides.journal.as_deref())?;
         path.push(Config::FILE_NAME);
-        Ok(path)
+        Ok((path, source))
     }
 
-    /// Try to find the config directory, first in a parent directory, then in
-    /// the user's home directory. If the directory cannot be found an error is
-    /// returned/
-    pub fn find_config_dir_path() -> anyhow::Result<PathBuf> {
-        Config::find_parent_config_dir_path().or_else(|_| Config::find_user_config_dir_path())
+    /// Try to find the config directory. If a journal name is given, it is
+    /// looked up in the journal registry. Otherwise it is searched for first in
+    /// a parent directory, then in the user's home directory. If the directory
+    /// cannot be found an error is returned.
+    ///
+    /// Returns the directory and how it was found.
+    pub fn find_config_dir_path(journal: Option<&str>) -> anyhow::Result<(PathBuf, ConfigSource)> {
+        if let Some(name) = journal {
+            let path = Registry::load()?.get(name)?.to_path_buf();
+            return Ok((path, ConfigSource::Registry(name.to_string())));
+        }
+        let legacy_user_path = Config::get_legacy_user_config_dir_path().ok();
+        Config::find_parent_config_dir_path()
+            .map(|path| {
+                if Some(&path) == legacy_user_path.as_ref() {
+                    (path, ConfigSource::User)
+                } else {
+                    (path, ConfigSource::Parent)
+                }
+            })
+            .or_else(|_| Ok((Config::find_user_config_dir_path()?, ConfigSource::User)))
     }
 
     /// Try to find a config directory in one of the parent directories.
@@ -99,23 +517,46 @@ impl Config {
 
     /// Try to find a config directory in the user's home directory.
     ///
-    /// If the file does not exist an error is returned.
+    /// If the file does not exist an error is returned. The XDG config
+    /// directory is only used if it contains a config file, since it is also
+    /// created to hold the journal registry.
     fn find_user_config_dir_path() -> anyhow::Result<PathBuf> {
         let path = Config::get_user_config_dir_path()?;
+        if path.join(Config::FILE_NAME).exists() {
+            return Ok(path);
+        }
+        let path = Config::get_legacy_user_config_dir_path()?;
         if path.exists() {
             Ok(path)
         } else {
             Err(anyhow::anyhow!(
-                "Could not find config file in user's home directory"
+                "Could not find config file in user's config or home directory"
             ))
         }
     }
 
-    /// Get the expected path to the config directory in the user's home directory.
+    /// Get the expected path to the user's config directory. On Linux this is
+    /// `$XDG_CONFIG_HOME/jarida`, elsewhere it is `.jarida` in the user's home
+    /// directory.
+    ///
+    /// The file may not exist. If the user's config/home directory could not be
+    /// found an error is returned.
+    pub fn get_user_config_dir_path() -> anyhow::Result<PathBuf> {
+        if cfg!(target_os = "linux") {
+            dirs_next::config_dir()
+                .map(|path| path.join(Config::XDG_DIR_NAME))
+                .ok_or_else(|| anyhow::anyhow!("Could not find user's config directory"))
+        } else {
+            Config::get_legacy_user_config_dir_path()
+        }
+    }
+
+    /// Get the expected path to the config directory in the user's home
+    /// directory, which was used before the XDG base directories were followed.
     ///
     /// The file may not exist. If the user's home directory could not be found
     /// an error is returned.
-    pub fn get_user_config_dir_path() -> anyhow::Result<PathBuf> {
+    pub fn get_legacy_user_config_dir_path() -> anyhow::Result<PathBuf> {
         dirs_next::home_dir()
             .map(|mut path| {
                 path.push(Config::DIR_NAME);
@@ -124,40 +565,332 @@ impl Config {
             .ok_or_else(|| anyhow::anyhow!("Could not find user's home directory"))
     }
 
+    /// Get the expected path to the directory containing the journal data for
+    /// the user's config directory. On Linux this is `$XDG_DATA_HOME/jarida`,
+    /// elsewhere it is the user's config directory itself.
+    ///
+    /// The directory may not exist. If the user's data/home directory could not
+    /// be found an error is returned.
+    pub fn get_user_data_dir_path() -> anyhow::Result<PathBuf> {
+        if cfg!(target_os = "linux") {
+            dirs_next::data_dir()
+                .map(|path| path.join(Config::XDG_DIR_NAME))
+                .ok_or_else(|| anyhow::anyhow!("Could not find user's data directory"))
+        } else {
+            Config::get_user_config_dir_path()
+        }
+    }
+
     /// Get the path to the directory containing journal data.
-    pub fn data_store_path(&self) -> PathBuf {
-        self.journal_dir
-            .clone()
-            .unwrap_or_else(|| Config::find_config_dir_path().unwrap())
+    ///
+    /// If not specified in config, it is the directory containing the config
+    /// file, except for the user's config directory, where it is the user's
+    /// data directory.
+    pub fn data_store_path(&self) -> anyhow::Result<PathBuf> {
+        if let Some(ref journal_dir) = self.journal_dir {
+            Ok(journal_dir.clone())
+        } else if Config::get_user_config_dir_path().ok().as_ref() == Some(&self.dir) {
+            Config::get_user_data_dir_path()
+        } else {
+            Ok(self.dir.clone())
+        }
+    }
+
+    /// Get the value of a setting, if it has one. The value given on the
+    /// command line or in the environment is preferred over the one in the
+    /// config file.
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<toml::Value>> {
+        let setting = Setting::find(key)?;
+        let values = toml::Value::try_from(self)?;
+        let value = values.get(setting.key).cloned();
+        if value.is_none() && setting.key == "editor" {
+            return Ok(Self::editor_from_env().map(|(_, editor)| toml::Value::String(editor)));
+        }
+        Ok(value)
+    }
+
+    /// Get the editor from the VISUAL or EDITOR environment variable, along
+    /// with the name of the variable it came from.
+    fn editor_from_env() -> Option<(&'static str, String)> {
+        ["VISUAL", "EDITOR"].into_iter().find_map(|name| {
+            std::env::var(name)
+                .ok()
+                .filter(|editor| !editor.trim().is_empty())
+                .map(|editor| (name, editor))
+        })
+    }
+
+    /// Get where the value of a setting came from.
+    pub fn get_origin(&self, key: &str) -> anyhow::Result<ValueOrigin> {
+        fn env_or_flag(name: &'static str, flag: &'static str, value: &Path) -> ValueOrigin {
+            if std::env::var_os(name).is_some_and(|env| Path::new(&env) == value) {
+                ValueOrigin::Env(name)
+            } else {
+                ValueOrigin::Flag(flag)
+            }
+        }
+
+        let setting = Setting::find(key)?;
+        match setting.key {
+            "editor" if self.overrides.editor.is_some() => {
+                return Ok(ValueOrigin::Flag("--editor"))
+            }
+            "journal_dir" => {
+                if let Some(ref dir) = self.overrides.journal_dir {
+                    return Ok(env_or_flag("JARIDA_DIR", "--journal-dir", dir));
+                }
+            }
+            _ => (),
+        }
+        if let Some(ref path) = self.path {
+            let table: toml::value::Table = toml::from_str(&fs::read_to_string(path)?)?;
+            let dashed = setting.key.replace('_', "-");
+            if table.contains_key(setting.key) || table.contains_key(&dashed) {
+                return Ok(ValueOrigin::File(path.clone()));
+            }
+        }
+        if setting.key == "editor" {
+            if let Some((name, _)) = Self::editor_from_env() {
+                return Ok(ValueOrigin::Env(name));
+            }
+        }
+        Ok(ValueOrigin::Default)
+    }
+
+    /// Set a setting in the config file, keeping the rest of the file, including
+    /// comments, as it is. If the setting is commented out, it is uncommented.
+    ///
+    /// The file is not changed if the result is an invalid config.
+    pub fn set_in_file(path: &Path, key: &str, value: &str) -> anyhow::Result<()> {
+        let setting = Setting::find(key)?;
+        let line = format!("{} = {}", setting.key, setting.parse_value(value)?);
+
+        /// Whether the line sets the key, possibly in a comment.
+        fn sets_key(line: &str, key: &str, commented: bool) -> bool {
+            let mut line = line.trim_start();
+            if commented {
+                match line.strip_prefix('#') {
+                    Some(rest) => line = rest.trim_start(),
+                    None => return false,
+                }
+            }
+            [key.to_string(), key.replace('_', "-")].iter().any(|key| {
+                line.strip_prefix(key.as_str())
+                    .is_some_and(|rest| rest.trim_start().starts_with('='))
+            })
+        }
+
+        let contents = fs::read_to_string(path)?;
+        let mut lines: Vec<_> = contents.lines().map(str::to_string).collect();
+        if let Some(i) = lines
+            .iter()
+            .position(|l| sets_key(l, setting.key, false))
+            .or_else(|| lines.iter().rposition(|l| sets_key(l, setting.key, true)))
+        {
+            lines[i] = line;
+        } else {
+            lines.push(line);
+        }
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+
+        contents
+            .parse::<Config>()
+            .context(format!("Could not set {}", setting.key))?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Get what is needed to unlock the journal.
+    pub fn unlock_mode(&self) -> UnlockMode {
+        self.unlock.unwrap_or_default()
+    }
+
+    /// Get how plaintext is padded before it is encrypted.
+    pub fn padding(&self) -> Padding {
+        self.padding.unwrap_or_default()
+    }
+
+    /// Whether to seal the index and hide file times.
+    pub fn hide_activity(&self) -> bool {
+        self.hide_activity.unwrap_or(false)
+    }
+
+    /// Get the time zone to display dates and times in, preferring the one
+    /// specified, if any, over the one in config.
+    pub fn display_timezone(&self, zone: Option<TimeZone>) -> TimeZone {
+        zone.or(self.display_timezone).unwrap_or_default()
+    }
+
+    /// Get the formatting string for date-times.
+    pub fn datetime_format(&self) -> &str {
+        match self.datetime_format.as_deref() {
+            None => DEFAULT_DATETIME_FORMAT,
+            Some(ISO8601) => ISO8601_DATETIME_FORMAT,
+            Some(format) => format,
+        }
+    }
+
+    /// Get the formatting string for dates.
+    pub fn date_format(&self) -> &str {
+        match self.date_format.as_deref() {
+            None => DEFAULT_DATE_FORMAT,
+            Some(ISO8601) => ISO8601_DATE_FORMAT,
+            Some(format) => format,
+        }
+    }
+
+    /// Get the path to the directory containing entry templates.
+    pub fn templates_dir_path(&self) -> PathBuf {
+        self.dir.join(Config::TEMPLATES_DIR_NAME)
+    }
+
+    /// Get the path to the file containing writing prompts, one per line.
+    pub fn prompts_file_path(&self) -> PathBuf {
+        self.dir.join(Config::PROMPTS_FILE_NAME)
     }
 
     /// Get the contents of a template config.toml file.
     pub fn template() -> &'static str {
         r#"
-# The path to your editor of choice. It will be used to write/edit journal
+# The command for your editor of choice. It will be used to write/edit journal
 # entries. Jarida considers the journal entry complete when the editor exits, so
 # if the editor exits early or sends its work to another process, an incomplete
-# entry will be saved.
-editor = ""
+# entry will be saved. The command may include arguments, quoted as in a shell.
+# Any `{file}` is replaced with the file to edit, otherwise the file is added at
+# the end. If omitted, the VISUAL or EDITOR environment variable is used.
+#editor = "code --wait"
 
 # Your name. This value is permanently associated with each journal entry and
-# together with the password are used to encrypt all journal data. There is no
-# way to recover the password if it is lost. If omitted you will be prompted
-# for it every time you run the program.
+# together with the password are used to encrypt all journal data. If omitted
+# you will be prompted for it every time you run the program.
 #user = "Your Name"
 
 # The password that, in combination with the user name, is used to encrypt all
-# journal data. There is no way to recover this password if it is lost. If
-# omitted you will be prompted for it every time you run the program.
+# journal data. If it is lost, the journal can only be unlocked with a recovery
+# key created beforehand with `jarida recovery-key create`. If omitted you will
+# be prompted for it every time you run the program.
+#
+# Anyone who can read this file can read your journal, so prefer
+# `password_command` or `password_file`. Only one of the three may be given.
+# The password may also be written to a file descriptor given in the
+# JARIDA_PASSWORD_FD environment variable, which takes priority over all three.
 #password = "your-password-here"
 
+# The command line that prints the password, e.g. from a password manager. Only
+# the first line of its output is used.
+#password_command = "pass show journal"
+
+# The file containing the password. Only the first line is used. The file
+# should only be readable by you.
+#password_file = "/path/to/password"
+
+# What is needed to unlock the journal, in addition to your name: "password",
+# "keyfile" or "password+keyfile". Use `jarida passwd --add-keyfile` to change
+# it, since the journal's key has to be encrypted again.
+#unlock = "password"
+
+# The keyfile used to unlock the journal, e.g. on a USB stick. Any file with
+# enough random content will do. Without it the journal can't be unlocked.
+#keyfile = "/path/to/keyfile"
+
+# What size encrypted entries and drafts are padded to, so the size of the
+# files doesn't reveal how long they are: "power-of-two" (at least 256 bytes),
+# "4k" or "64k" for the next multiple of that many bytes, or "none". Changing it
+# only affects data saved afterwards. Defaults to "power-of-two".
+#padding = "power-of-two"
+
+# Whether to hide when entries were written from anyone with access to the
+# journal's files, e.g. on a stolen laptop. The index, which lists entries in
+# the order they were written, is encrypted, and the times of the entries'
+# files are all set to 2000-01-01 after every command. The number of entries
+# can still be seen, and so can when jarida last ran, since the files' change
+# times (ctime) can't be reset. Defaults to false.
+#hide_activity = true
+
+# The name of the template new entries start from by default. Templates are
+# files in the `templates` directory next to this file, e.g. `templates/daily.md`
+# for the "daily" template. They may contain the following variables:
+#   {date}    - today's date, e.g. 2001-07-08
+#   {weekday} - the day of the week, e.g. Sunday
+#   {author}  - your name
+#   {prompt}  - a random writing prompt from `prompts.txt` next to this file,
+#               which contains one prompt per line
+#template = "daily"
+
+# The time zone dates and times are displayed in: "local" for your current time
+# zone, "written" for the time zone each entry was written in, or "utc".
+# Defaults to "local".
+#display_timezone = "written"
+
+# The formats dates and times are displayed in. Either "iso8601" or a format
+# description as documented at
+# https://time-rs.github.io/book/api/format-description.html
+# The date format is used for the {date} template variable.
+#datetime_format = "[weekday repr:short] [day padding:space]-[month repr:short]-[year] [hour repr:24]:[minute]"
+#date_format = "[year]-[month]-[day]"
+
 # An optional temporary working directory. All working data will be stored here.
 # If not specified, the OS's temporary directory will be used instead.
-#temp-dir = "<your-path-here>"
+#temp_dir = "<your-path-here>"
+
+# Whether to keep decrypted entries off disk while they are being written/edited
+# by keeping them in a RAM-backed directory, such as $XDG_RUNTIME_DIR or
+# /dev/shm, instead. A warning is printed if no such directory is available.
+#secure_temp = true
 
 # An optional directory to save all journal data in. If not specified, journal
-# data is stored in the same directory as the config file.
-#journal-dir = "<your-path-here>"
+# data is stored in the same directory as the config file. For the config file
+# in $XDG_CONFIG_HOME/jarida on Linux, it is stored in $XDG_DATA_HOME/jarida.
+#journal_dir = "<your-path-here>"
 "#
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn every_setting_can_be_set() {
+        for setting in SETTINGS {
+            let value = match (setting.key, setting.kind) {
+                ("editor", _) => r#"emacsclient -c -a "" --eval '(find-file "entry")'"#,
+                ("user", _) => r#"Ada "Countess" Lovelace"#,
+                ("password", _) => r#"correct horse 'battery' "staple" \"#,
+                ("password_command", _) => r#"pass show "journal/main key""#,
+                ("password_file", _) => "/home/ada/My Documents/password.txt",
+                ("keyfile", _) => "/media/usb stick/jarida.key",
+                ("temp_dir", _) => "/run/user/1000/jarida tmp",
+                ("journal_dir", _) => "/home/ada/Journals/My \"Diary\"",
+                ("template", _) => "daily review",
+                ("datetime_format", _) => "[weekday] [day] [month repr:long], [hour]:[minute]",
+                ("date_format", _) => "[year]-[month]-[day]",
+                (_, SettingKind::Bool) => "true",
+                (_, SettingKind::OneOf(options)) => options[0],
+                (key, _) => panic!("no example value for {}", key),
+            };
+            let value = setting.parse_value(value).unwrap();
+            let contents = format!("{} = {}", setting.key, value);
+            let cfg = contents.parse::<Config>().unwrap();
+            assert_eq!(cfg.get(setting.key).unwrap(), Some(value));
+        }
+    }
+
+    #[test]
+    fn set_in_file_keeps_the_rest_of_the_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(
+            file.path(),
+            "# Your name\nuser = \"me\"\n#journal-dir = \"<your-path-here>\"\n",
+        )
+        .unwrap();
+        Config::set_in_file(file.path(), "journal-dir", "/tmp").unwrap();
+        Config::set_in_file(file.path(), "secure_temp", "true").unwrap();
+        assert!(Config::set_in_file(file.path(), "temp_dir", "relative").is_err());
+        assert_eq!(
+            fs::read_to_string(file.path()).unwrap(),
+            "# Your name\nuser = \"me\"\njournal_dir = \"/tmp\"\nsecure_temp = true\n"
+        );
+    }
+}
@@ -3,10 +3,27 @@ use anyhow::Context as _;
 use fs_err as fs;
 use std::io::{Read as _, Write as _};
 use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
 
-use crate::security::{generate_db_salt, DataGuard, Open, Seal};
+use crate::common::copy_dir_all;
+use crate::security::{
+    generate_db_salt, CredentialGuard, DataGuard, Open, Record, RecordKind, Seal, SealFormat,
+    Suite, UnspecifiedError,
+};
 use crate::uuid::Uuid;
 
+/// The record the sealed index is encrypted as. No entry or draft has its id.
+const INDEX_RECORD: Record = Record {
+    id: Uuid::NIL,
+    kind: RecordKind::Index,
+    revision: 0,
+};
+/// The size of the revision in front of an entry's decrypted metadata
+const REVISION_LEN: usize = std::mem::size_of::<u64>();
+/// The time, in seconds since the Unix epoch, that file times are set to when
+/// activity is hidden (2000-01-01T00:00:00Z).
+const HIDDEN_FILE_TIME: i64 = 946_684_800;
+
 /// A record that has an ID
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Ided<T> {
@@ -22,26 +39,219 @@ pub struct Metadata {
     #[serde(with = "time::serde::rfc3339")]
     pub modified: time::OffsetDateTime,
     pub author: String,
+    /// The IANA name of the time zone the entry was written in, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// The user who last modified the entry, if it has been modified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_by: Option<String>,
 }
 
 impl Metadata {
     /// Create new metadata for journal entry by the specified user.
     pub fn new(username: &str) -> Self {
-        let now = time::OffsetDateTime::now_utc();
+        let now = now_local();
         Metadata {
             created: now,
             modified: now,
             author: username.to_string(),
+            timezone: local_timezone_name(),
+            modified_by: None,
         }
     }
 }
 
+/// Get the current date-time in the local time zone. If the local offset
+/// cannot be determined, UTC is used instead.
+pub fn now_local() -> time::OffsetDateTime {
+    time::OffsetDateTime::now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc())
+}
+
+/// Get the IANA name (e.g. Europe/Paris) of the local time zone, if it can be
+/// determined. Only names in the zoneinfo database are returned, so other
+/// values of `TZ`, e.g. POSIX time zone strings, are not mistaken for one.
+fn local_timezone_name() -> Option<String> {
+    fn zoneinfo_name(path: &str) -> Option<String> {
+        path.split_once("zoneinfo/")
+            .map(|(_, name)| name.to_string())
+    }
+
+    let name = match std::env::var("TZ") {
+        Ok(tz) if !tz.is_empty() => {
+            let tz = tz.trim_start_matches(':');
+            if tz.starts_with('/') {
+                zoneinfo_name(tz)
+            } else {
+                Some(tz.to_string())
+            }
+        }
+        _ => zoneinfo_name(std::fs::read_link("/etc/localtime").ok()?.to_str()?),
+    }?;
+    let zoneinfo = std::env::var_os("TZDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/usr/share/zoneinfo"));
+    Some(name).filter(|name| is_zoneinfo_name(&zoneinfo, name))
+}
+
+/// Whether the name is that of a time zone in the zoneinfo database in the
+/// specified directory.
+fn is_zoneinfo_name(zoneinfo: &Path, name: &str) -> bool {
+    name.split('/')
+        .all(|part| !part.is_empty() && part != "." && part != "..")
+        && zoneinfo.join(name).is_file()
+}
+
+/// Replace the files with the specified contents, so each is left with either
+/// its old or its new contents if jarida is interrupted or the system crashes.
+///
+/// The new contents are all written to temporary files and flushed to disk
+/// before any file is replaced, so the files can only get out of step with
+/// each other if jarida is interrupted in between.
+fn write_atomically(files: &[(&Path, &[u8])]) -> anyhow::Result<()> {
+    let mut temp_paths = Vec::with_capacity(files.len());
+    for (path, contents) in files {
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_data()?;
+        temp_paths.push(temp_path);
+    }
+    for ((path, _), temp_path) in files.iter().zip(&temp_paths) {
+        fs::rename(temp_path, path)?;
+    }
+    for (path, _) in files {
+        if let Some(dir) = path.parent() {
+            sync_dir(dir)?;
+        }
+    }
+    Ok(())
+}
+
+/// Flush the directory's entries, e.g. files renamed into it, to disk.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> anyhow::Result<()> {
+    fs::File::open(path)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
+
 /// A record containing the journal entry's metadata and content
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct MetadataAndContent {
     #[serde(flatten)]
     pub metadata: Metadata,
-    pub content: String,
+    pub content: Zeroizing<String>,
+}
+
+/// A record containing a journal entry that has not been saved yet
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Draft {
+    /// The entry being edited. Drafts of new entries have none.
+    pub entry: Option<Uuid>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub saved: time::OffsetDateTime,
+    pub content: Zeroizing<String>,
+}
+
+impl Draft {
+    /// Create a new draft of the specified entry, or of a new entry if `None`.
+    pub fn new(entry: Option<Uuid>, content: Zeroizing<String>) -> Self {
+        Draft {
+            entry,
+            saved: time::OffsetDateTime::now_utc(),
+            content,
+        }
+    }
+}
+
+/// The database's encryption key, encrypted with one user's credentials
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct KeySlot {
+    /// The user whose credentials the key is encrypted with. This is only
+    /// unknown for the single key of a store in the legacy format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// When the slot was added
+    #[serde(
+        default,
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub added: Option<time::OffsetDateTime>,
+    /// Whether the key is encrypted with a recovery key rather than a user's
+    /// credentials
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub recovery: bool,
+    /// The encrypted key
+    #[serde(with = "hex_bytes")]
+    pub key: Vec<u8>,
+}
+
+impl KeySlot {
+    /// Create a new key slot for the user.
+    pub fn new(user: &str, key: Vec<u8>) -> Self {
+        KeySlot {
+            user: Some(user.to_string()),
+            added: Some(now_local()),
+            recovery: false,
+            key,
+        }
+    }
+
+    /// Create a new key slot for a recovery key.
+    pub fn new_recovery(key: Vec<u8>) -> Self {
+        KeySlot {
+            user: None,
+            added: Some(now_local()),
+            recovery: true,
+            key,
+        }
+    }
+
+    /// Whether the slot may unlock the database for the user.
+    pub fn is_for(&self, user: &str) -> bool {
+        if self.recovery {
+            return false;
+        }
+        match self.user {
+            Some(ref slot_user) => slot_user == user,
+            None => true,
+        }
+    }
+}
+
+/// The contents of the key slots file
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct KeySlots {
+    #[serde(default, rename = "slot")]
+    slots: Vec<KeySlot>,
+}
+
+/// (De)serialize bytes as a string of hexadecimal digits.
+mod hex_bytes {
+    use serde::de::Error as _;
+
+    pub fn serialize<S: serde::Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        s.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let hex = <String as serde::Deserialize>::deserialize(d)?;
+        if !hex.is_ascii() || hex.len() % 2 != 0 {
+            return Err(D::Error::custom("invalid hexadecimal string"));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(D::Error::custom))
+            .collect()
+    }
 }
 
 /// A store of journal entries
@@ -49,14 +259,38 @@ pub struct MetadataAndContent {
 pub struct Store {
     /// The root directory of the data storage
     root: PathBuf,
+    /// The version of the on-disk format of the data storage
+    version: u32,
 }
 
 impl Store {
+    /// The version of the on-disk format written by this version of jarida.
+    pub const CURRENT_VERSION: u32 = 6;
+    /// The version of stores created before the format was versioned.
+    pub const LEGACY_VERSION: u32 = 1;
+    /// The first version in which encrypted data is padded.
+    pub const PADDED_VERSION: u32 = 3;
+    /// The first version in which encrypted data is authenticated along with
+    /// the record it is, instead of only the entry's id.
+    pub const RECORD_VERSION: u32 = 4;
+    /// The first version in which each entry, draft, etc. is encrypted with
+    /// its own key.
+    pub const SUBKEY_VERSION: u32 = 5;
+    /// The first version in which encrypted data starts with the cipher and
+    /// key generation it was encrypted with.
+    pub const SUITE_VERSION: u32 = 6;
+
     const ENTRIES_DIR_NAME: &'static str = "entries";
+    const DRAFTS_DIR_NAME: &'static str = "drafts";
     const SECURITY_DIR_NAME: &'static str = "security";
     const SALT_FILE_NAME: &'static str = "salt";
+    /// The single encrypted key of stores in the legacy format
     const KEY_FILE_NAME: &'static str = "key";
+    const KEY_SLOTS_FILE_NAME: &'static str = "slots.toml";
     const INDEX_FILE_NAME: &'static str = "index";
+    const SEALED_INDEX_FILE_NAME: &'static str = "index.sealed";
+    const VERSION_FILE_NAME: &'static str = "version";
+    const HEADER_FILE_NAME: &'static str = "header.toml";
 
     /// Get the directory containing all the entry data.
     fn get_entries_dir_path(&self) -> PathBuf {
@@ -70,6 +304,18 @@ impl Store {
         path
     }
 
+    /// Get the directory containing all the drafts.
+    fn get_drafts_dir_path(&self) -> PathBuf {
+        self.root.join(Self::DRAFTS_DIR_NAME)
+    }
+
+    /// Get the file path for the specified draft
+    fn get_draft_path(&self, id: Uuid) -> PathBuf {
+        let mut path = self.get_drafts_dir_path();
+        path.push(format!("{}", id));
+        path
+    }
+
     /// Get the file path for the database salt.
     fn get_salt_path(&self) -> PathBuf {
         let mut path = self.root.join(Self::SECURITY_DIR_NAME);
@@ -84,12 +330,37 @@ impl Store {
         path
     }
 
+    /// Get the file path for the key slots.
+    fn get_key_slots_path(&self) -> PathBuf {
+        let mut path = self.root.join(Self::SECURITY_DIR_NAME);
+        path.push(Self::KEY_SLOTS_FILE_NAME);
+        path
+    }
+
     /// Get the file path for the index file, which contains the list of entry
     /// Uuids in ascending order.
     fn get_index_path(&self) -> PathBuf {
         self.root.join(Self::INDEX_FILE_NAME)
     }
 
+    /// Get the file path for the sealed index file, which is used instead of
+    /// the index file when activity is hidden.
+    fn get_sealed_index_path(&self) -> PathBuf {
+        self.root.join(Self::SEALED_INDEX_FILE_NAME)
+    }
+
+    /// Get the file path for the version file, which contains the version of
+    /// the on-disk format.
+    fn get_version_path(&self) -> PathBuf {
+        self.root.join(Self::VERSION_FILE_NAME)
+    }
+
+    /// Get the file path for the header, which records how new data is
+    /// encrypted.
+    fn get_header_path(&self) -> PathBuf {
+        self.root.join(Self::HEADER_FILE_NAME)
+    }
+
     /// Open the journal stored at the specified path.
     pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Store> {
         fn ignore_already_existing(error: std::io::Error) -> std::io::Result<()> {
@@ -101,34 +372,177 @@ impl Store {
         }
 
         let path = path.as_ref();
-        let store = Store {
+        let mut store = Store {
             root: path.to_path_buf(),
+            version: Self::CURRENT_VERSION,
         };
         let security_path = path.join(Self::SECURITY_DIR_NAME);
         let entries_path = path.join(Self::ENTRIES_DIR_NAME);
+        let drafts_path = path.join(Self::DRAFTS_DIR_NAME);
         fs::create_dir_all(path).or_else(ignore_already_existing)?;
         fs::create_dir(security_path).or_else(ignore_already_existing)?;
         fs::create_dir(entries_path).or_else(ignore_already_existing)?;
+        fs::create_dir(drafts_path).or_else(ignore_already_existing)?;
 
-        // Make sure the is a unique salt value
+        // A store without a salt has never been used, so it can start out in
+        // the current format.
         let salt_path = store.get_salt_path();
-        if !salt_path.exists() {
+        let is_new = !salt_path.exists();
+
+        // Make sure the is a unique salt value
+        if is_new {
             let mut f = fs::File::create(salt_path)?;
             f.write_all(&generate_db_salt().unwrap())?;
-        }
-        // Make sure the key file exists, even if it is empty.
-        let key_path = store.get_key_path();
-        if !key_path.exists() {
-            fs::File::create(key_path)?;
+            store.set_suite(Suite::default())?;
         }
         // Make sure the index files exists, even if it is empty.
         let index_path = store.get_index_path();
-        if !index_path.exists() {
+        if !index_path.exists() && !store.get_sealed_index_path().exists() {
             fs::File::create(index_path)?;
         }
+        // Make sure the version file exists. Existing stores without one
+        // predate versioning and use the legacy format.
+        let version_path = store.get_version_path();
+        if version_path.exists() {
+            let contents = fs::read_to_string(&version_path)?;
+            store.version = contents
+                .trim()
+                .parse()
+                .context(format!("Invalid store version {:?}", contents.trim()))?;
+        } else {
+            store.set_version(if is_new {
+                Self::CURRENT_VERSION
+            } else {
+                Self::LEGACY_VERSION
+            })?;
+        }
         Ok(store)
     }
 
+    /// Get the version of the store's on-disk format.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Get how data in the store is encrypted.
+    fn seal_format(&self) -> SealFormat {
+        Self::seal_format_of(self.version)
+    }
+
+    /// Get how data is encrypted in stores of the specified version.
+    fn seal_format_of(version: u32) -> SealFormat {
+        if version >= Self::SUITE_VERSION {
+            SealFormat::Suite
+        } else if version >= Self::SUBKEY_VERSION {
+            SealFormat::Subkey
+        } else if version >= Self::RECORD_VERSION {
+            SealFormat::Record
+        } else if version >= Self::PADDED_VERSION {
+            SealFormat::Padded
+        } else {
+            SealFormat::Unpadded
+        }
+    }
+
+    /// Get the cipher and keys that new data is encrypted with. Stores without
+    /// a header use AES-256-GCM and the first key generation.
+    pub fn get_suite(&self) -> anyhow::Result<Suite> {
+        let path = self.get_header_path();
+        if !path.exists() {
+            return Ok(Suite::default());
+        }
+        toml::from_str(&fs::read_to_string(&path)?)
+            .context(format!("Could not parse {}", path.display()))
+    }
+
+    /// Record the cipher and keys that new data is encrypted with.
+    pub fn set_suite(&mut self, suite: Suite) -> anyhow::Result<()> {
+        let path = self.get_header_path();
+        write_atomically(&[(&path, toml::to_string(&suite)?.as_bytes())])
+    }
+
+    /// Record that the store's on-disk format is now the specified version.
+    pub fn set_version(&mut self, version: u32) -> anyhow::Result<()> {
+        let contents = format!("{}\n", version);
+        write_atomically(&[(&self.get_version_path(), contents.as_bytes())])
+            .context("Could not write version file")?;
+        self.version = version;
+        Ok(())
+    }
+
+    /// Make sure the store's on-disk format can be used by this version of
+    /// jarida.
+    ///
+    /// Returns an error if the store is in an older format, which must be
+    /// upgraded first, or in a newer format, which cannot be read.
+    pub fn check_version(&self) -> anyhow::Result<()> {
+        use std::cmp::Ordering;
+        match self.version.cmp(&Self::CURRENT_VERSION) {
+            Ordering::Equal => Ok(()),
+            Ordering::Less => Err(anyhow::anyhow!(
+                "{} uses an older data format (v{}). Run `jarida upgrade` to upgrade it to v{}.",
+                self.root.display(),
+                self.version,
+                Self::CURRENT_VERSION
+            )),
+            Ordering::Greater => Err(anyhow::anyhow!(
+                "{} uses a newer data format (v{}) than this version of jarida supports (v{})",
+                self.root.display(),
+                self.version,
+                Self::CURRENT_VERSION
+            )),
+        }
+    }
+
+    /// Copy the entire store to a new directory next to it and return the path
+    /// to the copy.
+    pub fn backup(&self) -> anyhow::Result<PathBuf> {
+        let timestamp = time::OffsetDateTime::now_utc().format(
+            time::macros::format_description!("[year][month][day]T[hour][minute][second]"),
+        )?;
+        let mut name = self
+            .root
+            .file_name()
+            .context("Could not determine the name of the store directory")?
+            .to_os_string();
+        name.push(format!(".backup-v{}-{}", self.version, timestamp));
+        let path = self.root.with_file_name(name);
+        if path.exists() {
+            anyhow::bail!("Backup {} already exists", path.display());
+        }
+        copy_dir_all(&self.root, &path)
+            .context(format!("Could not back up the store to {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Set the access and modification times of all entries, drafts and the
+    /// index to the same fixed time, so they don't reveal when entries were
+    /// written, edited or read.
+    pub fn hide_file_times(&self) -> anyhow::Result<()> {
+        fn hide_times(path: &Path) -> anyhow::Result<()> {
+            if path.is_dir() {
+                for entry in fs::read_dir(path)? {
+                    hide_times(&entry?.path())?;
+                }
+            }
+            let time = filetime::FileTime::from_unix_time(HIDDEN_FILE_TIME, 0);
+            filetime::set_file_times(path, time, time)
+                .context(format!("Could not set the times of {}", path.display()))
+        }
+
+        for path in [
+            self.get_entries_dir_path(),
+            self.get_drafts_dir_path(),
+            self.get_index_path(),
+            self.get_sealed_index_path(),
+        ] {
+            if path.exists() {
+                hide_times(&path)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Get the database's unique salt (for use in encryption).
     pub fn get_salt(&self) -> anyhow::Result<Vec<u8>> {
         let mut buf = Vec::new();
@@ -138,29 +552,56 @@ impl Store {
         Ok(buf)
     }
 
-    /// Get the database's encryption key. This key is used to encrypt/decrypt
-    /// all data in the database. However, it is encrypted using the user's
-    /// name and password.
-    pub fn get_key(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+    /// Get the database's key slots. Each holds the key used to
+    /// encrypt/decrypt all data in the database, encrypted with one user's
+    /// credentials. There are none if nothing has been put in the database.
+    ///
+    /// Stores in the legacy format have a single key, for an unknown user.
+    pub fn get_key_slots(&self) -> anyhow::Result<Vec<KeySlot>> {
+        let path = self.get_key_slots_path();
+        if path.exists() {
+            let slots: KeySlots = toml::from_str(&fs::read_to_string(&path)?)
+                .context(format!("Could not parse {}", path.display()))?;
+            return Ok(slots.slots);
+        }
         let path = self.get_key_path();
         if path.exists() {
             let mut buf = Vec::new();
             let size = fs::File::open(path)
                 .context("Could not open key file")?
                 .read_to_end(&mut buf)?;
-            Ok(if size > 0 { Some(buf) } else { None })
-        } else {
-            Ok(None)
+            if size > 0 {
+                return Ok(vec![KeySlot {
+                    user: None,
+                    added: None,
+                    recovery: false,
+                    key: buf,
+                }]);
+            }
         }
+        Ok(Vec::new())
+    }
+
+    /// Replace the database's key slots. Every key must be the same key
+    /// encrypted with different credentials, since there would be no way to
+    /// decrypt existing entries otherwise.
+    pub fn set_key_slots(&mut self, slots: &[KeySlot]) -> anyhow::Result<()> {
+        let path = self.get_key_slots_path();
+        let contents = toml::to_string(&KeySlots {
+            slots: slots.to_vec(),
+        })?;
+        // Losing the key slots means losing the journal, so never leave a
+        // partially written file behind.
+        write_atomically(&[(&path, contents.as_bytes())])
     }
 
-    /// Update the database's encryption key. It must already be encrypted with
-    /// the user's name and password. Note that the key itself should _never_
-    /// change as then there will be no way to decrypt existing entries in the
-    /// database. It can be reencrypted with a new username and password, however.
-    pub fn update_key(&mut self, encrypted_key: &[u8]) -> anyhow::Result<()> {
-        let mut f = fs::File::create(self.get_key_path()).context("Could not open key file")?;
-        f.write_all(encrypted_key)?;
+    /// Remove the single key of a store in the legacy format, once it has been
+    /// moved to a key slot.
+    pub fn remove_legacy_key(&mut self) -> anyhow::Result<()> {
+        let path = self.get_key_path();
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
         Ok(())
     }
 
@@ -174,6 +615,7 @@ impl Store {
             store: self,
             username,
             guard,
+            hide_activity: false,
         }
     }
 }
@@ -187,6 +629,8 @@ pub struct GuardedStore<'a> {
     pub username: &'a str,
     /// The guard used for encryption/decryption.
     guard: &'a mut DataGuard,
+    /// Whether the index is sealed
+    hide_activity: bool,
 }
 
 impl<'a> GuardedStore<'a> {
@@ -204,51 +648,233 @@ impl<'a> GuardedStore<'a> {
         path
     }
 
-    /// Set/update the content of a journal entry. If the journal entry already
-    /// exists it's content will be overwritten. The content is encrypted prior
-    /// to writing.
-    fn write_content(&mut self, uuid: Uuid, content: String) -> anyhow::Result<()> {
-        let mut f = fs::File::create(self.get_entry_content_path(uuid))
-            .context(format!("Could not create content file for {}", uuid))?;
-        f.write_all(&content.seal(uuid, self.guard)?)?;
+    /// Split the revision of a journal entry off the front of its decrypted
+    /// metadata. Entries in stores older than v4 have no revision.
+    fn split_revision(&self, path: &Path, metadata: &mut Vec<u8>) -> anyhow::Result<u64> {
+        use std::convert::TryInto as _;
+        if self.store.version() < Store::RECORD_VERSION {
+            return Ok(0);
+        }
+        let revision = metadata
+            .get(..REVISION_LEN)
+            .and_then(|revision| revision.try_into().ok())
+            .map(u64::from_le_bytes)
+            .context(format!("{} is too short", path.display()))?;
+        metadata.drain(..REVISION_LEN);
+        Ok(revision)
+    }
+
+    /// Get the revision of a journal entry.
+    fn read_revision(&mut self, uuid: Uuid) -> anyhow::Result<u64> {
+        Ok(self.read_metadata_and_revision(uuid)?.0)
+    }
+
+    /// Set/update the metadata and content of a journal entry. If the journal
+    /// entry already exists it will be overwritten. Both are encrypted prior
+    /// to writing. The content is bound to the revision, which is encrypted
+    /// along with the metadata, so neither can be swapped for another
+    /// revision's without the other.
+    ///
+    /// The entry is written in the format of the specified version, which is
+    /// the current one except while upgrading the store.
+    fn write_entry(
+        &mut self,
+        version: u32,
+        uuid: Uuid,
+        revision: u64,
+        metadata: &Metadata,
+        content: Zeroizing<String>,
+    ) -> anyhow::Result<()> {
+        let content = self.seal(
+            version,
+            &Record::new(uuid, RecordKind::Content, revision),
+            content,
+        )?;
+        let metadata = Zeroizing::new(toml::to_string(metadata)?);
+        let mut plaintext = Vec::with_capacity(REVISION_LEN + metadata.len());
+        if version >= Store::RECORD_VERSION {
+            plaintext.extend_from_slice(&revision.to_le_bytes());
+        }
+        plaintext.extend_from_slice(metadata.as_bytes());
+        // The metadata holds the revision, so it isn't bound to one itself.
+        let meta = self.seal(
+            version,
+            &Record::new(uuid, RecordKind::Metadata, 0),
+            plaintext,
+        )?;
+
+        write_atomically(&[
+            (&self.get_entry_content_path(uuid), &content),
+            (&self.get_entry_metadata_path(uuid), &meta),
+        ])
+        .context(format!("Could not write entry {}", uuid))
+    }
+
+    /// Encrypt data to be written to the store, as it is encrypted for the
+    /// specified version.
+    fn seal<T: Seal>(
+        &mut self,
+        version: u32,
+        record: &Record,
+        data: T,
+    ) -> Result<Vec<u8>, UnspecifiedError> {
+        match Store::seal_format_of(version) {
+            SealFormat::Suite => data.seal(record, self.guard),
+            format => self.guard.seal_in_format(format, record, data.into_bytes()),
+        }
+    }
+
+    /// Decrypt data read from the store, as it was encrypted for the store's
+    /// version.
+    fn open<T: Open>(
+        &mut self,
+        record: &Record,
+        ciphertext: Vec<u8>,
+    ) -> Result<T, UnspecifiedError> {
+        match self.store.seal_format() {
+            SealFormat::Suite => T::open(record, ciphertext, self.guard),
+            format => T::from_bytes(self.guard.open_in_format(format, record, ciphertext)?),
+        }
+    }
+
+    /// Decrypt a file and encrypt it again in the format of the specified
+    /// version. The file is replaced in one step, so it is never left
+    /// partially written.
+    fn reseal_file(&mut self, version: u32, record: &Record, path: &Path) -> anyhow::Result<()> {
+        let ciphertext = fs::read(path)?;
+        let plaintext: Zeroizing<Vec<u8>> = Zeroizing::new(self.read_resealed(version, |db| {
+            db.open(record, ciphertext.clone())
+                .context(format!("Could not decrypt {}", path.display()))
+        })?);
+        let ciphertext = self.seal(version, record, plaintext.to_vec())?;
+        write_atomically(&[(path, &ciphertext)])
+    }
+
+    /// Read from the store as if it were in the format of the specified
+    /// version.
+    fn read_as<T>(
+        &mut self,
+        version: u32,
+        read: impl FnOnce(&mut Self) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let actual = std::mem::replace(&mut self.store.version, version);
+        let result = read(self);
+        self.store.version = actual;
+        result
+    }
+
+    /// Read data that an interrupted upgrade may already have encrypted again
+    /// in the format of the version being upgraded to. That format is tried
+    /// first, since data in the store's format can't be mistaken for it.
+    fn read_resealed<T>(
+        &mut self,
+        version: u32,
+        read: impl Fn(&mut Self) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        self.read_as(version, &read).or_else(|_| read(self))
+    }
+
+    /// Decrypt all entries and drafts and encrypt them again in the format of
+    /// the version the store is being upgraded to.
+    ///
+    /// Data that is already in that format is simply encrypted again, so an
+    /// interrupted upgrade can be resumed.
+    pub fn reseal_all(&mut self, version: u32) -> anyhow::Result<()> {
+        for uuid in self.read_resealed(version, Self::get_uuids)? {
+            // The content is replaced before the metadata, so they may be in
+            // different formats, and each is read on its own.
+            let (revision, metadata) =
+                self.read_resealed(version, |db| db.read_metadata_and_revision(uuid))?;
+            let content =
+                self.read_resealed(version, |db| db.read_content_of_revision(uuid, revision))?;
+            self.write_entry(version, uuid, revision, &metadata, content)?;
+        }
+        for id in self.get_draft_ids()? {
+            let path = self.store.get_draft_path(id);
+            self.reseal_file(version, &Record::new(id, RecordKind::Draft, 0), &path)?;
+        }
+        let path = self.store.get_sealed_index_path();
+        if path.exists() {
+            self.reseal_file(version, &INDEX_RECORD, &path)?;
+        }
+        Ok(())
+    }
+
+    /// Encrypt all entries, drafts and the sealed index again with a new
+    /// suite, e.g. to use a new generation of keys or switch to another cipher. The new
+    /// suite is recorded first, so data written in the meantime uses it too.
+    /// Until everything has been encrypted again, the store holds data from
+    /// both suites, which can still be read.
+    pub fn set_suite(&mut self, suite: Suite) -> anyhow::Result<()> {
+        self.store.set_suite(suite)?;
+        self.guard.set_suite(suite);
+        self.reseal_all(Store::CURRENT_VERSION)
+    }
+
+    /// Set whether the index is sealed, so the order entries were written in
+    /// can't be read without unlocking the journal. The index is sealed or
+    /// unsealed right away if needed.
+    pub fn set_hide_activity(&mut self, hide: bool) -> anyhow::Result<()> {
+        self.hide_activity = hide;
+        if self.store.get_sealed_index_path().exists() != hide {
+            let uuids = self.get_uuids()?;
+            self.write_index(&uuids)?;
+        }
         Ok(())
     }
 
     /// Get the decrypted contents of a journal entry.
-    fn read_content(&mut self, uuid: Uuid) -> anyhow::Result<String> {
+    fn read_content(&mut self, uuid: Uuid) -> anyhow::Result<Zeroizing<String>> {
+        // The content can only be decrypted with the revision of the metadata
+        // it belongs to.
+        let revision = self.read_revision(uuid)?;
+        self.read_content_of_revision(uuid, revision)
+    }
+
+    /// Get the decrypted contents of the specified revision of a journal
+    /// entry.
+    fn read_content_of_revision(
+        &mut self,
+        uuid: Uuid,
+        revision: u64,
+    ) -> anyhow::Result<Zeroizing<String>> {
         let path = self.get_entry_content_path(uuid);
         if path.exists() {
             let mut f =
                 fs::File::open(&path).context(format!("Could not open {}", path.display()))?;
             let mut buf = Vec::new();
             f.read_to_end(&mut buf)?;
-            Ok(Open::open(uuid, buf, self.guard)?)
+            self.open(&Record::new(uuid, RecordKind::Content, revision), buf)
+                .context(format!(
+                    "Could not decrypt {}. It may not belong with the entry's metadata.",
+                    path.display()
+                ))
         } else {
             Err(anyhow::anyhow!("Invalid id {}", uuid))
         }
     }
 
-    /// Set/update the metadata for a journal entry. If the metadata already
-    /// exists it will be overwritten. The metadata is encrypted prior to
-    /// writing.
-    fn write_metadata(&mut self, uuid: Uuid, metadata: &Metadata) -> anyhow::Result<()> {
-        let mut f = fs::File::create(self.get_entry_metadata_path(uuid))
-            .context(format!("Could not create metadata file for {}", uuid))?;
-        f.write_all(&toml::to_string(metadata)?.seal(uuid, self.guard)?)?;
-        Ok(())
-    }
-
     /// Get the decrypted metadata for a journal entry.
     fn read_metadata(&mut self, uuid: Uuid) -> anyhow::Result<Metadata> {
+        Ok(self.read_metadata_and_revision(uuid)?.1)
+    }
+
+    /// Get the revision and decrypted metadata for a journal entry.
+    fn read_metadata_and_revision(&mut self, uuid: Uuid) -> anyhow::Result<(u64, Metadata)> {
         let path = self.get_entry_metadata_path(uuid);
         if path.exists() {
             let mut f =
                 fs::File::open(&path).context(format!("Could not open {}", path.display()))?;
             let mut buf = Vec::new();
             f.read_to_end(&mut buf)?;
-            let buf: Vec<_> = Open::open(uuid, buf, self.guard)?;
-            let meta: Metadata = toml::from_slice(&buf)?;
-            Ok(meta)
+            let mut buf: Zeroizing<Vec<u8>> = Zeroizing::new(
+                self.open(&Record::new(uuid, RecordKind::Metadata, 0), buf)
+                    .context(format!("Could not decrypt {}", path.display()))?,
+            );
+            let revision = self.split_revision(&path, &mut buf)?;
+            let meta: Metadata =
+                toml::from_slice(&buf).context(format!("Could not parse {}", path.display()))?;
+            Ok((revision, meta))
         } else {
             Err(anyhow::anyhow!("Invalid id {}", uuid))
         }
@@ -256,18 +882,23 @@ impl<'a> GuardedStore<'a> {
 
     /// Insert a new entry into the database with the associated metadata.
     /// Returns an ID for the new entry.
-    pub fn insert(&mut self, meta: &Metadata, entry: String) -> anyhow::Result<Uuid> {
+    pub fn insert(&mut self, meta: &Metadata, entry: Zeroizing<String>) -> anyhow::Result<Uuid> {
         let uuid = Uuid::random().unwrap();
         fs::create_dir_all(self.store.get_entry_path(uuid))?;
-        self.write_content(uuid, entry)?;
-        self.write_metadata(uuid, meta)?;
+        self.write_entry(Store::CURRENT_VERSION, uuid, 0, meta, entry)?;
 
         // Add the new UUID to the index file
-        let mut f = fs::OpenOptions::new()
-            .append(true)
-            .open(self.store.get_index_path())
-            .context("Could not open index file")?;
-        f.write_all(format!("{}\n", uuid).as_bytes())?;
+        if self.hide_activity {
+            let mut uuids = self.get_uuids()?;
+            uuids.push(uuid);
+            self.write_index(&uuids)?;
+        } else {
+            let mut f = fs::OpenOptions::new()
+                .append(true)
+                .open(self.store.get_index_path())
+                .context("Could not open index file")?;
+            f.write_all(format!("{}\n", uuid).as_bytes())?;
+        }
         Ok(uuid)
     }
 
@@ -276,23 +907,112 @@ impl<'a> GuardedStore<'a> {
         &mut self,
         uuid: Uuid,
         modified: time::OffsetDateTime,
-        entry: String,
+        entry: Zeroizing<String>,
     ) -> anyhow::Result<()> {
-        let mut meta = self.read_metadata(uuid)?;
+        let (revision, mut meta) = self.read_metadata_and_revision(uuid)?;
         meta.modified = modified;
-        self.write_metadata(uuid, &meta)?;
-        self.write_content(uuid, entry)
+        meta.modified_by = Some(self.username.to_string());
+        self.write_entry(Store::CURRENT_VERSION, uuid, revision + 1, &meta, entry)
+    }
+
+    /// Permanently remove an existing entry.
+    pub fn remove(&mut self, uuid: Uuid) -> anyhow::Result<()> {
+        let path = self.store.get_entry_path(uuid);
+        if !path.exists() {
+            anyhow::bail!("Invalid id {}", uuid);
+        }
+        let uuids: Vec<_> = self
+            .get_uuids()?
+            .into_iter()
+            .filter(|id| *id != uuid)
+            .collect();
+        self.write_index(&uuids)?;
+        fs::remove_dir_all(path)?;
+        Ok(())
+    }
+
+    /// Save a draft. If the draft already exists it will be overwritten. The
+    /// draft is encrypted prior to writing.
+    ///
+    /// The draft is replaced in one step, so the previous draft is kept if
+    /// saving the new one fails.
+    pub fn write_draft(&mut self, id: Uuid, draft: &Draft) -> anyhow::Result<()> {
+        let record = Record::new(id, RecordKind::Draft, 0);
+        let ciphertext = Zeroizing::new(toml::to_string(draft)?).seal(&record, self.guard)?;
+        let path = self.store.get_draft_path(id);
+        write_atomically(&[(&path, &ciphertext)])
+            .context(format!("Could not create draft file for {}", id))
+    }
+
+    /// Get a decrypted draft.
+    pub fn read_draft(&mut self, id: Uuid) -> anyhow::Result<Draft> {
+        let path = self.store.get_draft_path(id);
+        if path.exists() {
+            let mut f =
+                fs::File::open(&path).context(format!("Could not open {}", path.display()))?;
+            let mut buf = Vec::new();
+            f.read_to_end(&mut buf)?;
+            let buf: Zeroizing<Vec<u8>> = Zeroizing::new(
+                self.open(&Record::new(id, RecordKind::Draft, 0), buf)
+                    .context(format!("Could not decrypt {}", path.display()))?,
+            );
+            let draft: Draft =
+                toml::from_slice(&buf).context(format!("Could not parse {}", path.display()))?;
+            Ok(draft)
+        } else {
+            Err(anyhow::anyhow!("Invalid draft id {}", id))
+        }
+    }
+
+    /// Whether the specified draft exists.
+    pub fn has_draft(&self, id: Uuid) -> bool {
+        self.store.get_draft_path(id).exists()
+    }
+
+    /// Remove a draft, if it exists.
+    pub fn remove_draft(&mut self, id: Uuid) -> anyhow::Result<()> {
+        let path = self.store.get_draft_path(id);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Get the ids of all the drafts
+    pub fn get_draft_ids(&self) -> anyhow::Result<Vec<Uuid>> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(self.store.get_drafts_dir_path())? {
+            let entry = entry.context("Failed to read a draft")?;
+            // Skip drafts that were being written when jarida was interrupted.
+            if entry.path().extension() == Some("tmp".as_ref()) {
+                continue;
+            }
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            ids.push(
+                file_name
+                    .parse()
+                    .context(format!("Invalid draft file: {}", file_name))?,
+            );
+        }
+        Ok(ids)
     }
 
     /// Get the uuids of all the journal entries
-    pub fn get_uuids(&self) -> anyhow::Result<Vec<Uuid>> {
-        use std::io::{BufRead as _, BufReader};
-        let f = fs::File::open(self.store.get_index_path()).context("Could not open index file")?;
-        let reader = BufReader::new(f);
+    pub fn get_uuids(&mut self) -> anyhow::Result<Vec<Uuid>> {
+        let path = self.store.get_sealed_index_path();
+        let index: Zeroizing<String> = if path.exists() {
+            let ciphertext = fs::read(&path).context("Could not open index file")?;
+            self.open(&INDEX_RECORD, ciphertext)
+                .context("Could not decrypt index file")?
+        } else {
+            Zeroizing::new(
+                fs::read_to_string(self.store.get_index_path())
+                    .context("Could not open index file")?,
+            )
+        };
 
         let mut uuids = Vec::new();
-        for line in reader.lines() {
-            let line = line?;
+        for line in index.lines() {
             uuids.push(
                 line.parse::<Uuid>()
                     .context(format!("Could not parse uuid {}", line))?,
@@ -314,7 +1034,7 @@ impl<'a> GuardedStore<'a> {
     }
 
     /// Get the content of the journal entries with the specified uuids
-    pub fn get_content(&mut self, uuids: &[Uuid]) -> Vec<Ided<anyhow::Result<String>>> {
+    pub fn get_content(&mut self, uuids: &[Uuid]) -> Vec<Ided<anyhow::Result<Zeroizing<String>>>> {
         uuids
             .iter()
             .cloned()
@@ -346,6 +1066,39 @@ impl<'a> GuardedStore<'a> {
             .collect()
     }
 
+    /// Encrypt the database's key with the user's new credentials. From now
+    /// on, only the new credentials can unlock the database for the user.
+    /// The user is given a key slot if they don't already have one.
+    pub fn set_key_slot(&mut self, user: &str, cred_guard: &CredentialGuard) -> anyhow::Result<()> {
+        let encrypted_key = self.encrypt_key(cred_guard)?;
+        let mut slots = self.store.get_key_slots()?;
+        match slots
+            .iter_mut()
+            .find(|slot| slot.user.as_deref() == Some(user))
+        {
+            Some(slot) => slot.key = encrypted_key,
+            None => slots.push(KeySlot::new(user, encrypted_key)),
+        }
+        self.store.set_key_slots(&slots)
+    }
+
+    /// Encrypt the database's key with a recovery key, replacing any previous
+    /// recovery key.
+    pub fn set_recovery_key_slot(&mut self, cred_guard: &CredentialGuard) -> anyhow::Result<()> {
+        let encrypted_key = self.encrypt_key(cred_guard)?;
+        let mut slots = self.store.get_key_slots()?;
+        slots.retain(|slot| !slot.recovery);
+        slots.push(KeySlot::new_recovery(encrypted_key));
+        self.store.set_key_slots(&slots)
+    }
+
+    /// Encrypt the database's key with the credentials.
+    fn encrypt_key(&self, cred_guard: &CredentialGuard) -> anyhow::Result<Vec<u8>> {
+        cred_guard
+            .encrypt_data_key(self.guard)
+            .map_err(|_| anyhow::anyhow!("Could not encrypt database key"))
+    }
+
     /// Recreate the index file based on the contexts of the "entries" directory
     pub fn index(&mut self) -> anyhow::Result<()> {
         // The "entries" directory should contain a folder for each journal
@@ -375,12 +1128,251 @@ impl<'a> GuardedStore<'a> {
         }
         entries.sort_unstable_by_key(|entry| entry.data);
 
-        // Overwrite the index file
-        let mut f = fs::File::create(self.store.get_index_path())?;
-        for entry in entries {
-            writeln!(f, "{}", entry.uuid)?;
-        }
+        let uuids: Vec<_> = entries.into_iter().map(|entry| entry.uuid).collect();
+        self.write_index(&uuids)
+    }
 
+    /// Overwrite the index file with the specified uuids.
+    /// The index is sealed if activity is hidden.
+    fn write_index(&mut self, uuids: &[Uuid]) -> anyhow::Result<()> {
+        let index: String = uuids.iter().map(|uuid| format!("{}\n", uuid)).collect();
+        let (path, old_path, contents) = if self.hide_activity {
+            let ciphertext = index.seal(&INDEX_RECORD, self.guard)?;
+            (
+                self.store.get_sealed_index_path(),
+                self.store.get_index_path(),
+                ciphertext,
+            )
+        } else {
+            (
+                self.store.get_index_path(),
+                self.store.get_sealed_index_path(),
+                index.into_bytes(),
+            )
+        };
+        // Replace the index in one step, so it is never left partially
+        // written and the entries can't be lost.
+        write_atomically(&[(&path, &contents)])?;
+        if old_path.exists() {
+            fs::remove_file(&old_path).context(format!(
+                "Wrote {} but could not remove {}",
+                path.display(),
+                old_path.display()
+            ))?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::security::unlocked_guard;
+
+    #[test]
+    fn only_zoneinfo_names_are_time_zones() {
+        let zoneinfo = tempfile::tempdir().unwrap();
+        fs::create_dir(zoneinfo.path().join("Europe")).unwrap();
+        fs::write(zoneinfo.path().join("Europe/Paris"), "TZif").unwrap();
+        assert!(is_zoneinfo_name(zoneinfo.path(), "Europe/Paris"));
+        for name in [
+            "",
+            "Europe",
+            "Europe/",
+            "Europe/Berlin",
+            "EST5EDT,M3.2.0,M11.1.0",
+            "../zoneinfo/Europe/Paris",
+            "Europe/../Europe/Paris",
+        ] {
+            assert!(!is_zoneinfo_name(zoneinfo.path(), name), "{:?}", name);
+        }
+    }
+
+    /// Write an entry in the v2 format.
+    fn write_v2_entry(db: &mut GuardedStore, content: &str) -> Uuid {
+        let uuid = Uuid::random().unwrap();
+        fs::create_dir_all(db.store.get_entry_path(uuid)).unwrap();
+        let meta = Metadata::new("me");
+        db.write_entry(2, uuid, 0, &meta, Zeroizing::new(content.to_string()))
+            .unwrap();
+        uuid
+    }
+
+    /// Write a draft of an entry in the v2 format.
+    fn write_v2_draft(db: &mut GuardedStore, entry: Uuid, content: &str) -> Uuid {
+        let id = Uuid::random().unwrap();
+        let draft = Draft::new(Some(entry), Zeroizing::new(content.to_string()));
+        let draft = db
+            .seal(
+                2,
+                &Record::new(id, RecordKind::Draft, 0),
+                toml::to_string(&draft).unwrap(),
+            )
+            .unwrap();
+        fs::write(db.store.get_draft_path(id), draft).unwrap();
+        id
+    }
+
+    /// Write a store in the v2 format, with an entry and a draft, then upgrade
+    /// it and read them back.
+    #[test]
+    fn stores_can_be_upgraded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::open(dir.path()).unwrap();
+        store.set_version(2).unwrap();
+        let mut guard = unlocked_guard();
+        let mut db = store.guard(&mut guard, "me");
+        let uuid = write_v2_entry(&mut db, "Hello");
+        db.write_index(&[uuid]).unwrap();
+        let id = write_v2_draft(&mut db, uuid, "Hello again");
+
+        crate::migrate::migrate(&mut db).unwrap();
+        assert_eq!(db.store.version(), Store::CURRENT_VERSION);
+        assert_eq!(db.get_uuids().unwrap(), [uuid]);
+        assert_eq!(db.read_metadata(uuid).unwrap().author, "me");
+        assert_eq!(*db.read_content(uuid).unwrap(), "Hello");
+        let draft = db.read_draft(id).unwrap();
+        assert_eq!(draft.entry, Some(uuid));
+        assert_eq!(*draft.content, "Hello again");
+    }
+
+    /// Upgrade a v2 store, stopping partway through, then resume the upgrade.
+    #[test]
+    fn interrupted_upgrades_can_be_resumed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::open(dir.path()).unwrap();
+        store.set_version(2).unwrap();
+        let mut guard = unlocked_guard();
+        let mut db = store.guard(&mut guard, "me");
+        let uuids = [
+            write_v2_entry(&mut db, "Hello"),
+            write_v2_entry(&mut db, ""),
+            write_v2_entry(&mut db, "Bye"),
+        ];
+        db.write_index(&uuids).unwrap();
+        let id = write_v2_draft(&mut db, uuids[0], "Hello again");
+
+        // Content is replaced before metadata, so an entry can be left with
+        // only its content in the v3 format.
+        let content = Zeroizing::new("Bye".to_string());
+        let record = Record::new(uuids[2], RecordKind::Content, 0);
+        let content = db.seal(Store::PADDED_VERSION, &record, content).unwrap();
+        fs::write(db.get_entry_content_path(uuids[2]), content).unwrap();
+
+        // Drafts are encrypted again after entries, so a draft that can't be
+        // decrypted stops the upgrade once the entries are in the v3 format.
+        let bad_path = db.store.get_draft_path(Uuid::random().unwrap());
+        fs::write(&bad_path, b"not a draft").unwrap();
+        assert!(crate::migrate::migrate(&mut db).is_err());
+        assert_eq!(db.store.version(), 2);
+        fs::remove_file(&bad_path).unwrap();
+
+        // Everything may also be in the v3 format before the version is.
+        db.reseal_all(Store::PADDED_VERSION).unwrap();
+        assert_eq!(db.store.version(), 2);
+
+        crate::migrate::migrate(&mut db).unwrap();
+        assert_eq!(db.store.version(), Store::CURRENT_VERSION);
+        assert_eq!(db.get_uuids().unwrap(), uuids);
+        assert_eq!(*db.read_content(uuids[0]).unwrap(), "Hello");
+        assert_eq!(*db.read_content(uuids[1]).unwrap(), "");
+        assert_eq!(*db.read_content(uuids[2]).unwrap(), "Bye");
+        assert_eq!(*db.read_draft(id).unwrap().content, "Hello again");
+    }
+
+    #[test]
+    fn content_is_bound_to_the_revision() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::open(dir.path()).unwrap();
+        let mut guard = unlocked_guard();
+        let mut db = store.guard(&mut guard, "me");
+        let uuid = db
+            .insert(&Metadata::new("me"), Zeroizing::new("Hello".to_string()))
+            .unwrap();
+        let content_path = db.get_entry_content_path(uuid);
+        let old_content = fs::read(&content_path).unwrap();
+        let now = time::OffsetDateTime::now_utc();
+        db.update(uuid, now, Zeroizing::new("Hello again".to_string()))
+            .unwrap();
+        assert_eq!(db.read_revision(uuid).unwrap(), 1);
+        assert_eq!(*db.read_content(uuid).unwrap(), "Hello again");
+
+        fs::write(&content_path, old_content).unwrap();
+        assert!(db.read_content(uuid).is_err());
+    }
+
+    #[test]
+    fn sealed_index_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::open(dir.path()).unwrap();
+        let mut guard = unlocked_guard();
+        let mut db = store.guard(&mut guard, "me");
+        let insert = |db: &mut GuardedStore, content: &str| {
+            db.insert(&Metadata::new("me"), Zeroizing::new(content.to_string()))
+                .unwrap()
+        };
+        let mut uuids = vec![insert(&mut db, "one"), insert(&mut db, "two")];
+
+        db.set_hide_activity(true).unwrap();
+        let sealed_path = db.store.get_sealed_index_path();
+        assert!(!db.store.get_index_path().exists());
+        let sealed = fs::read(&sealed_path).unwrap();
+        let uuid = uuids[0].to_string();
+        assert!(!sealed.windows(uuid.len()).any(|w| w == uuid.as_bytes()));
+        assert_eq!(db.get_uuids().unwrap(), uuids);
+        uuids.push(insert(&mut db, "three"));
+        assert_eq!(db.get_uuids().unwrap(), uuids);
+
+        db.set_hide_activity(false).unwrap();
+        assert!(!sealed_path.exists());
+        assert_eq!(db.get_uuids().unwrap(), uuids);
+    }
+
+    #[test]
+    fn file_times_can_be_hidden() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::open(dir.path()).unwrap();
+        let mut guard = unlocked_guard();
+        let mut db = store.guard(&mut guard, "me");
+        db.set_hide_activity(true).unwrap();
+        let uuid = db
+            .insert(&Metadata::new("me"), Zeroizing::new("Hello".to_string()))
+            .unwrap();
+        let id = Uuid::random().unwrap();
+        let draft = Draft::new(Some(uuid), Zeroizing::new("Hello again".to_string()));
+        db.write_draft(id, &draft).unwrap();
+
+        db.store.hide_file_times().unwrap();
+        let hidden = filetime::FileTime::from_unix_time(HIDDEN_FILE_TIME, 0);
+        for path in [
+            db.store.get_entries_dir_path(),
+            db.store.get_entry_path(uuid),
+            db.get_entry_content_path(uuid),
+            db.get_entry_metadata_path(uuid),
+            db.store.get_drafts_dir_path(),
+            db.store.get_draft_path(id),
+            db.store.get_sealed_index_path(),
+        ] {
+            let metadata = fs::metadata(&path).unwrap();
+            let modified = filetime::FileTime::from_last_modification_time(&metadata);
+            let accessed = filetime::FileTime::from_last_access_time(&metadata);
+            assert_eq!(modified, hidden, "{}", path.display());
+            assert_eq!(accessed, hidden, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn key_slots_round_trip() {
+        let slots = KeySlots {
+            slots: vec![KeySlot::new("alice", vec![0, 1, 0xab, 0xff])],
+        };
+        let contents = toml::to_string(&slots).unwrap();
+        let parsed: KeySlots = toml::from_str(&contents).unwrap();
+        assert_eq!(parsed.slots.len(), 1);
+        assert_eq!(parsed.slots[0].user.as_deref(), Some("alice"));
+        assert_eq!(parsed.slots[0].key, vec![0, 1, 0xab, 0xff]);
+        assert!(parsed.slots[0].is_for("alice"));
+        assert!(!parsed.slots[0].is_for("bob"));
+        assert!(toml::from_str::<KeySlots>("[[slot]]\nkey = \"abc\"\n").is_err());
+    }
+}
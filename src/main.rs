@@ -6,21 +6,19 @@ mod cli;
 mod common;
 mod config;
 mod db;
+mod migrate;
+mod recovery;
+mod registry;
 mod security;
+mod shamir;
+mod template;
 mod uuid;
+mod workfile;
 
 use cli::Args;
-use common::get_and_validate_credentials;
-use config::Config;
-use db::Store;
 
 fn main() -> anyhow::Result<()> {
     pretty_env_logger::init();
 
-    let command = Args::parse();
-    let cfg = Config::find()?;
-    let mut db = Store::open(cfg.data_store_path())?;
-    let (username, mut data_guard) = get_and_validate_credentials(&cfg, &mut db)?;
-    let mut db = db.guard(&mut data_guard, &username);
-    command.run(&cfg, &mut db)
+    Args::parse().run()
 }
@@ -0,0 +1,119 @@
+use anyhow::Context as _;
+
+use crate::db::{GuardedStore, Store};
+use crate::security::Suite;
+
+/// A step that upgrades a store's on-disk format from one version to the next.
+struct Migration {
+    /// The version being upgraded from. The store is at `from + 1` afterwards.
+    from: u32,
+    /// A short, human readable description of what changed.
+    description: &'static str,
+    /// Perform the upgrade.
+    run: fn(&mut GuardedStore) -> anyhow::Result<()>,
+}
+
+/// Every migration, in ascending order of the version they upgrade from.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        description: "Move the journal's key to a key slot for the current user",
+        run: move_key_to_slot,
+    },
+    Migration {
+        from: 2,
+        description: "Pad entries and drafts so their sizes don't reveal their length",
+        run: pad_sealed_data,
+    },
+    Migration {
+        from: 3,
+        description: "Bind each entry's metadata and content to each other and their revision",
+        run: bind_records,
+    },
+    Migration {
+        from: 4,
+        description: "Encrypt each entry and draft with its own key",
+        run: use_subkeys,
+    },
+    Migration {
+        from: 5,
+        description: "Record the cipher each entry and draft is encrypted with",
+        run: add_suites,
+    },
+];
+
+/// Move the single key of a legacy store to a key slot for the user who
+/// unlocked it.
+fn move_key_to_slot(db: &mut GuardedStore) -> anyhow::Result<()> {
+    let mut slots = db.store.get_key_slots()?;
+    for slot in slots.iter_mut().filter(|slot| slot.user.is_none()) {
+        slot.user = Some(db.username.to_string());
+    }
+    db.store.set_key_slots(&slots)?;
+    db.store.remove_legacy_key()
+}
+
+/// Encrypt all entries and drafts again, now that plaintext is padded before
+/// it is encrypted.
+fn pad_sealed_data(db: &mut GuardedStore) -> anyhow::Result<()> {
+    db.reseal_all(Store::PADDED_VERSION)
+}
+
+/// Encrypt all entries and drafts again, now that what record they are,
+/// including the revision of entries, is authenticated along with them.
+fn bind_records(db: &mut GuardedStore) -> anyhow::Result<()> {
+    db.reseal_all(Store::RECORD_VERSION)
+}
+
+/// Encrypt all entries and drafts again, now that each is encrypted with a
+/// key derived from the journal's key.
+fn use_subkeys(db: &mut GuardedStore) -> anyhow::Result<()> {
+    db.reseal_all(Store::SUBKEY_VERSION)
+}
+
+/// Encrypt all entries and drafts again, now that the cipher and key
+/// generation are stored in front of them, and record the suite new data is
+/// encrypted with.
+fn add_suites(db: &mut GuardedStore) -> anyhow::Result<()> {
+    db.store.set_suite(Suite::default())?;
+    db.reseal_all(Store::SUITE_VERSION)
+}
+
+/// Upgrade the store, one version at a time, until it is in the current format.
+///
+/// The store's version is updated after each successful step, so an
+/// interrupted upgrade can be resumed.
+pub fn migrate(db: &mut GuardedStore) -> anyhow::Result<()> {
+    while db.store.version() < Store::CURRENT_VERSION {
+        let from = db.store.version();
+        let migration = MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == from)
+            .context(format!("Don't know how to upgrade from v{}", from))?;
+        println!(
+            "Upgrading v{} to v{}: {}",
+            from,
+            from + 1,
+            migration.description
+        );
+        (migration.run)(db).context(format!("Failed to upgrade from v{}", from))?;
+        db.store.set_version(from + 1)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn migrations_are_contiguous() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.from, Store::LEGACY_VERSION + i as u32);
+        }
+        assert_eq!(
+            Store::LEGACY_VERSION + MIGRATIONS.len() as u32,
+            Store::CURRENT_VERSION
+        );
+    }
+}
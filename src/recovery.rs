@@ -0,0 +1,165 @@
+use ring::digest;
+use zeroize::Zeroize as _;
+
+use crate::security::{UnspecifiedError, SYSTEM_RNG};
+
+/// The digits of Crockford's base 32, which avoids letters that are easily
+/// confused with each other
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+/// The number of random bytes in a recovery key
+const KEY_LEN: usize = 20;
+/// The number of base 32 digits encoding the random bytes
+const KEY_DIGITS: usize = KEY_LEN * 8 / 5;
+/// The number of base 32 digits in the checksum
+const CHECKSUM_DIGITS: usize = 4;
+/// The number of digits printed in each group
+const GROUP_LEN: usize = 4;
+
+/// A high-entropy key that can unlock a journal when the user's credentials
+/// are lost.
+///
+/// It is printed in groups of base 32 digits, with a checksum at the end to
+/// catch typos, e.g. `7G2K-...-QX4M`.
+pub struct RecoveryKey([u8; KEY_LEN]);
+
+impl Drop for RecoveryKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl RecoveryKey {
+    /// The user name the recovery key's slot is encrypted for.
+    pub const USER: &'static str = "recovery";
+
+    /// Generate a new, random recovery key.
+    pub fn random() -> Result<Self, UnspecifiedError> {
+        use ring::rand::SecureRandom as _;
+        let mut key = [0u8; KEY_LEN];
+        SYSTEM_RNG.fill(&mut key)?;
+        Ok(RecoveryKey(key))
+    }
+
+    /// Create a recovery key from its secret, e.g. as rebuilt from shares.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        use std::convert::TryInto as _;
+        Ok(RecoveryKey(bytes.try_into().map_err(|_| {
+            anyhow::anyhow!("A recovery key has {} bytes, not {}", KEY_LEN, bytes.len())
+        })?))
+    }
+
+    /// Get the secret used to encrypt the recovery key's slot.
+    pub fn secret(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Get the base 32 digits of the checksum of the key.
+    fn checksum(&self) -> [u8; CHECKSUM_DIGITS] {
+        let hash = digest::digest(&digest::SHA256, &self.0);
+        let bits = u32::from_be_bytes([hash.as_ref()[0], hash.as_ref()[1], hash.as_ref()[2], 0]);
+        let mut checksum = [0u8; CHECKSUM_DIGITS];
+        for (i, digit) in checksum.iter_mut().enumerate() {
+            *digit = ALPHABET[(bits >> (27 - 5 * i) & 0x1f) as usize];
+        }
+        checksum
+    }
+}
+
+impl std::fmt::Display for RecoveryKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Every 5 bytes of the key are 8 base 32 digits.
+        let mut digits = Vec::with_capacity(KEY_DIGITS + CHECKSUM_DIGITS);
+        for chunk in self.0.chunks(5) {
+            let mut bits = [0u8; 8];
+            bits[3..].copy_from_slice(chunk);
+            let bits = u64::from_be_bytes(bits);
+            for i in 0..8 {
+                digits.push(ALPHABET[(bits >> (35 - 5 * i) & 0x1f) as usize]);
+            }
+        }
+        digits.extend(self.checksum());
+        let groups: Vec<_> = digits
+            .chunks(GROUP_LEN)
+            .map(|group| String::from_utf8_lossy(group).into_owned())
+            .collect();
+        write!(f, "{}", groups.join("-"))
+    }
+}
+
+impl std::str::FromStr for RecoveryKey {
+    type Err = anyhow::Error;
+
+    /// Parse a recovery key as it is printed. Case, spaces and dashes are
+    /// ignored, and letters that are easily confused with digits are read as
+    /// those digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(|c| {
+                let c = match c.to_ascii_uppercase() {
+                    'O' => '0',
+                    'I' | 'L' => '1',
+                    c => c,
+                };
+                ALPHABET
+                    .iter()
+                    .position(|&digit| digit as char == c)
+                    .map(|value| value as u64)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid character {:?} in recovery key", c))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if digits.len() != KEY_DIGITS + CHECKSUM_DIGITS {
+            anyhow::bail!(
+                "A recovery key has {} characters, not counting dashes",
+                KEY_DIGITS + CHECKSUM_DIGITS
+            );
+        }
+
+        let mut key = [0u8; KEY_LEN];
+        for (chunk, digits) in key.chunks_mut(5).zip(digits.chunks(8)) {
+            let bits = digits.iter().fold(0u64, |bits, digit| bits << 5 | digit);
+            chunk.copy_from_slice(&bits.to_be_bytes()[3..]);
+        }
+        let key = RecoveryKey(key);
+        let checksum: Vec<_> = digits[KEY_DIGITS..]
+            .iter()
+            .map(|&digit| ALPHABET[digit as usize])
+            .collect();
+        if checksum != key.checksum() {
+            anyhow::bail!("The recovery key has a typo");
+        }
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recovery_key_round_trips() {
+        let key = RecoveryKey::random().unwrap();
+        let printed = key.to_string();
+        assert_eq!(printed.len(), 36 + 8);
+        let parsed: RecoveryKey = printed.parse().unwrap();
+        assert_eq!(parsed.secret(), key.secret());
+        // Case, spacing and confusable letters don't matter.
+        let sloppy = printed.to_lowercase().replace('-', " ").replace('0', "o");
+        let parsed: RecoveryKey = sloppy.parse().unwrap();
+        assert_eq!(parsed.secret(), key.secret());
+    }
+
+    #[test]
+    fn recovery_key_typos_are_caught() {
+        let printed = RecoveryKey::random().unwrap().to_string();
+        let mut typo = printed.clone().into_bytes();
+        typo[0] = if typo[0] == b'2' { b'3' } else { b'2' };
+        assert!(String::from_utf8(typo)
+            .unwrap()
+            .parse::<RecoveryKey>()
+            .is_err());
+        assert!(printed[1..].parse::<RecoveryKey>().is_err());
+        assert!(printed.replace('-', "U").parse::<RecoveryKey>().is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Context as _;
+use fs_err as fs;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+/// The global registry of named journals, which maps each name to the
+/// journal's configuration directory.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Registry {
+    #[serde(default)]
+    journals: BTreeMap<String, PathBuf>,
+}
+
+impl Registry {
+    /// The name of the registry file.
+    pub const FILE_NAME: &'static str = "journals.toml";
+
+    /// Get the path to the registry file, which is kept in the user's
+    /// configuration directory, or in the legacy one in the user's home
+    /// directory if it has not been migrated yet. The file may not exist.
+    pub fn path() -> anyhow::Result<PathBuf> {
+        let path = Config::get_user_config_dir_path()?.join(Registry::FILE_NAME);
+        if !path.exists() {
+            let legacy_path = Config::get_legacy_user_config_dir_path()?.join(Registry::FILE_NAME);
+            if legacy_path.exists() {
+                return Ok(legacy_path);
+            }
+        }
+        Ok(path)
+    }
+
+    /// Load the registry. If the registry file does not exist, the registry is
+    /// empty.
+    pub fn load() -> anyhow::Result<Registry> {
+        let path = Registry::path()?;
+        if !path.exists() {
+            return Ok(Registry::default());
+        }
+        let contents = fs::read_to_string(&path)?;
+        toml::from_str(&contents).context(format!("Could not parse {}", path.display()))
+    }
+
+    /// Save the registry, creating the user's configuration directory if
+    /// needed.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Registry::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Get the configuration directory of the named journal.
+    ///
+    /// Returns an error if there is no journal with that name.
+    pub fn get(&self, name: &str) -> anyhow::Result<&Path> {
+        self.journals
+            .get(name)
+            .map(PathBuf::as_path)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "There is no journal named {:?}. Run `jarida journals` to see them.",
+                    name
+                )
+            })
+    }
+
+    /// Add a journal, or replace the configuration directory of an existing
+    /// one.
+    pub fn insert(&mut self, name: String, config_dir: PathBuf) {
+        self.journals.insert(name, config_dir);
+    }
+
+    /// Remove the named journal. Returns an error if there is no journal with
+    /// that name.
+    pub fn remove(&mut self, name: &str) -> anyhow::Result<PathBuf> {
+        self.journals
+            .remove(name)
+            .ok_or_else(|| anyhow::anyhow!("There is no journal named {:?}", name))
+    }
+
+    /// Change the configuration directory of every journal in `from` to `to`.
+    pub fn relocate(&mut self, from: &Path, to: &Path) {
+        for path in self.journals.values_mut() {
+            if path == from {
+                *path = to.to_path_buf();
+            }
+        }
+    }
+
+    /// Iterate over the names and configuration directories of every journal
+    /// in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Path)> {
+        self.journals
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.as_path()))
+    }
+}
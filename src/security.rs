@@ -1,7 +1,8 @@
 use super::uuid::Uuid;
 use once_cell::sync::Lazy;
-use ring::{self, aead, digest, pbkdf2, rand};
+use ring::{self, aead, digest, hkdf, pbkdf2, rand};
 use std::num::NonZeroU32;
+use zeroize::{Zeroize as _, Zeroizing};
 
 /// The size of an encryption key, which must match the encryption algorithm
 const KEY_LEN: usize = digest::SHA256_OUTPUT_LEN;
@@ -9,6 +10,231 @@ const KEY_LEN: usize = digest::SHA256_OUTPUT_LEN;
 pub type Key = [u8; KEY_LEN];
 /// The database portion of a salt used for deriving keys from username and passwords.
 pub type DbSalt = [u8; 16];
+/// The size of the nonce stored with encrypted data
+const NONCE_LEN: usize = aead::NONCE_LEN;
+/// The size of the nonce stored with key slots, and with data before v5 of the
+/// store's format. Only the first `NONCE_LEN` bytes are used.
+const LEGACY_NONCE_LEN: usize = 16;
+/// Identifies the keys derived from the data key for each entry, draft, etc.
+const SUBKEY_INFO: &[u8] = b"jarida record key";
+/// The size of the suite in front of data encrypted in v6 of the store's
+/// format: the cipher's id and the key generation
+const SUITE_LEN: usize = 1 + std::mem::size_of::<u32>();
+/// The size of the length at the start of padded plaintext
+const PADDING_HEADER_LEN: usize = std::mem::size_of::<u32>();
+/// The smallest size plaintext is padded to when padding to a power of two
+const MIN_PADDED_LEN: usize = 256;
+
+/// How plaintext is padded before it is encrypted, so the size of the
+/// ciphertext doesn't reveal the size of the plaintext.
+#[derive(Debug, Default, PartialEq, Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum Padding {
+    /// No padding, the ciphertext is as long as the plaintext
+    #[serde(rename = "none")]
+    None,
+    /// Pad to the next power of two, at least 256 bytes
+    #[default]
+    #[serde(rename = "power-of-two")]
+    PowerOfTwo,
+    /// Pad to the next multiple of 4 KiB
+    #[serde(rename = "4k")]
+    Bucket4K,
+    /// Pad to the next multiple of 64 KiB
+    #[serde(rename = "64k")]
+    Bucket64K,
+}
+
+impl Padding {
+    /// Get the size that data of the specified size is padded to.
+    fn padded_len(self, len: usize) -> usize {
+        fn round_up(len: usize, bucket: usize) -> usize {
+            len.div_ceil(bucket).max(1) * bucket
+        }
+
+        match self {
+            Padding::None => len,
+            Padding::PowerOfTwo => len
+                .max(MIN_PADDED_LEN)
+                .checked_next_power_of_two()
+                .unwrap_or(len),
+            Padding::Bucket4K => round_up(len, 4 * 1024),
+            Padding::Bucket64K => round_up(len, 64 * 1024),
+        }
+    }
+}
+
+impl std::fmt::Display for Padding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Padding::None => write!(f, "none"),
+            Padding::PowerOfTwo => write!(f, "power-of-two"),
+            Padding::Bucket4K => write!(f, "4k"),
+            Padding::Bucket64K => write!(f, "64k"),
+        }
+    }
+}
+
+/// The algorithm data is encrypted with
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub enum Cipher {
+    /// AES-256 in Galois/Counter Mode, which is fastest on CPUs with AES
+    /// instructions
+    #[default]
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm,
+    /// ChaCha20-Poly1305, which is faster on CPUs without them
+    #[serde(rename = "chacha20-poly1305")]
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    /// Every cipher, in the order of their ids
+    const ALL: [Cipher; 2] = [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305];
+
+    /// Get the implementation of the cipher.
+    fn algorithm(self) -> &'static aead::Algorithm {
+        match self {
+            Cipher::Aes256Gcm => &aead::AES_256_GCM,
+            Cipher::ChaCha20Poly1305 => &aead::CHACHA20_POLY1305,
+        }
+    }
+
+    /// Get the id the cipher is identified by in encrypted data.
+    fn id(self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => 1,
+            Cipher::ChaCha20Poly1305 => 2,
+        }
+    }
+
+    /// Get the cipher identified by the id in encrypted data.
+    fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|cipher| cipher.id() == id)
+    }
+}
+
+impl std::fmt::Display for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Cipher::Aes256Gcm => write!(f, "aes-256-gcm"),
+            Cipher::ChaCha20Poly1305 => write!(f, "chacha20-poly1305"),
+        }
+    }
+}
+
+impl std::str::FromStr for Cipher {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|cipher| cipher.to_string() == s)
+            .ok_or_else(|| format!("Unknown cipher {:?}", s))
+    }
+}
+
+/// The cipher and keys that data is encrypted with. It is stored in front of
+/// each piece of encrypted data, so data encrypted with different suites can
+/// be decrypted side by side.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Suite {
+    /// The algorithm data is encrypted with
+    #[serde(default)]
+    pub cipher: Cipher,
+    /// Which keys derived from the data key are used, which changes each time
+    /// the journal is encrypted again
+    #[serde(default)]
+    pub key_generation: u32,
+}
+
+impl Suite {
+    /// Get the bytes that are stored in front of encrypted data.
+    fn to_bytes(self) -> [u8; SUITE_LEN] {
+        let mut bytes = [0; SUITE_LEN];
+        bytes[0] = self.cipher.id();
+        bytes[1..].copy_from_slice(&self.key_generation.to_le_bytes());
+        bytes
+    }
+
+    /// Get the suite from the bytes in front of encrypted data.
+    fn from_slice(bytes: &[u8]) -> Option<Self> {
+        use std::convert::TryInto as _;
+        Some(Suite {
+            cipher: Cipher::from_id(*bytes.first()?)?,
+            key_generation: u32::from_le_bytes(bytes.get(1..SUITE_LEN)?.try_into().ok()?),
+        })
+    }
+}
+
+/// An encryption key that is never printed, is kept out of swap where
+/// possible and is wiped from memory when dropped.
+pub struct SecretKey(Box<Key>);
+
+impl SecretKey {
+    /// Create a key of zeros, to be filled in.
+    fn zeroed() -> Self {
+        let key = SecretKey(Box::new([0; KEY_LEN]));
+        lock_memory(&key.0[..]);
+        key
+    }
+
+    /// Create a key from a slice of the right length.
+    fn from_slice(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != KEY_LEN {
+            return None;
+        }
+        let mut key = Self::zeroed();
+        key.copy_from_slice(bytes);
+        Some(key)
+    }
+}
+
+impl std::ops::Deref for SecretKey {
+    type Target = Key;
+
+    fn deref(&self) -> &Key {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for SecretKey {
+    fn deref_mut(&mut self) -> &mut Key {
+        &mut self.0
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "SecretKey(..)")
+    }
+}
+
+/// Try to keep the memory from being swapped to disk. This is only a
+/// precaution, so failure is not an error.
+///
+/// The memory is never unlocked. Locks on a page don't stack, so unlocking the
+/// memory of one key would also unlock any other key on the same page. jarida
+/// only holds a few keys, which are wiped when dropped, so this costs little.
+#[cfg(unix)]
+fn lock_memory(bytes: &[u8]) {
+    // SAFETY: The pointer and length describe memory that is valid for the
+    // duration of the call.
+    if unsafe { libc::mlock(bytes.as_ptr() as *const libc::c_void, bytes.len()) } != 0 {
+        log::debug!(
+            "Could not lock key in memory: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_memory(_bytes: &[u8]) {}
 
 pub static SYSTEM_RNG: Lazy<rand::SystemRandom> = Lazy::new(rand::SystemRandom::new);
 
@@ -30,51 +256,41 @@ impl std::convert::From<ring::error::Unspecified> for UnspecifiedError {
 
 impl std::error::Error for UnspecifiedError {}
 
-/// A source of Nonces (numbers that you only use once).
-#[derive(Debug, Clone)]
-pub struct Nonce(u128);
+/// A number that is only used once with a key.
+#[derive(Debug, Clone, Copy)]
+pub struct Nonce([u8; NONCE_LEN]);
 
 impl Nonce {
-    /// Generate a new, random source for Nonces.
+    /// Generate a new, random Nonce.
     pub fn random() -> Result<Nonce, UnspecifiedError> {
         use rand::SecureRandom as _;
-        let mut buf = [0u8; Self::len()];
+        let mut buf = [0u8; NONCE_LEN];
         SYSTEM_RNG.fill(&mut buf)?;
-        Ok(Nonce(u128::from_le_bytes(buf)))
-    }
-
-    /// Encoded the present nonce value as a little-endian array of bytes.
-    pub fn to_le_bytes(&self) -> [u8; Self::len()] {
-        self.0.to_le_bytes()
+        Ok(Nonce(buf))
     }
 
-    /// Decode a Nonce for a little-endian array of bytes.
-    pub fn from_le_bytes(bytes: [u8; Self::len()]) -> Self {
-        Nonce(u128::from_le_bytes(bytes))
+    /// Get the bytes of the Nonce.
+    pub fn to_bytes(self) -> [u8; NONCE_LEN] {
+        self.0
     }
 
-    /// The number of bytes needed to represent the Nonce.
-    pub const fn len() -> usize {
-        std::mem::size_of::<u128>()
-    }
-}
-
-impl aead::NonceSequence for Nonce {
-    fn advance(&mut self) -> Result<aead::Nonce, ring::error::Unspecified> {
+    /// Get the Nonce at the start of the bytes stored with encrypted data.
+    fn from_slice(bytes: &[u8]) -> Option<Self> {
         use std::convert::TryInto as _;
-        let nonce = aead::Nonce::assume_unique_for_key(
-            self.to_le_bytes()[..12]
-                .try_into()
-                .map_err(|_| ring::error::Unspecified)?,
-        );
-        self.0 += 1;
-        Ok(nonce)
+        Some(Nonce(bytes.get(..NONCE_LEN)?.try_into().ok()?))
     }
 }
 
-impl aead::NonceSequence for &mut Nonce {
+/// Hands out a single Nonce, so the key it is bound to can only seal or open
+/// one message with it.
+struct SingleUse(Option<Nonce>);
+
+impl aead::NonceSequence for SingleUse {
     fn advance(&mut self) -> Result<aead::Nonce, ring::error::Unspecified> {
-        (*self).advance()
+        self.0
+            .take()
+            .map(|nonce| aead::Nonce::assume_unique_for_key(nonce.0))
+            .ok_or(ring::error::Unspecified)
     }
 }
 
@@ -88,22 +304,40 @@ pub fn generate_db_salt() -> Result<DbSalt, UnspecifiedError> {
     Ok(salt)
 }
 
-/// Derive a key suitable for encrypt based on the database's salt and the
-/// user's name and password.
-fn derive_key_from_credentials(db_salt: &DbSalt, username: &str, password: &str) -> Key {
+/// Combine the user's password and keyfile into the secret that keys are
+/// derived from. Either may be omitted, depending on how the journal is
+/// unlocked. A password alone is used as it is, so journals that only use a
+/// password can still be unlocked.
+pub fn credential_secret(password: Option<&str>, keyfile: Option<&[u8]>) -> Zeroizing<Vec<u8>> {
+    let mut secret = Zeroizing::new(Vec::new());
+    if let Some(password) = password {
+        secret.extend(password.as_bytes());
+    }
+    if let Some(keyfile) = keyfile {
+        if password.is_some() {
+            secret.push(0);
+        }
+        secret.extend(digest::digest(&digest::SHA256, keyfile).as_ref());
+    }
+    secret
+}
+
+/// Derive a key suitable for encrypt based on the database's salt, the
+/// user's name and their secret (see `credential_secret`).
+fn derive_key_from_credentials(db_salt: &DbSalt, username: &str, secret: &[u8]) -> SecretKey {
     // Generate a salt based on the database's unique salt and the user's name.
-    let mut salt = Vec::with_capacity(db_salt.len() + username.as_bytes().len());
+    let mut salt = Vec::with_capacity(db_salt.len() + username.len());
     salt.extend(db_salt);
     salt.extend(username.as_bytes());
 
     // Derive key suitable for encryption/decryption
-    let mut key: Key = [0; KEY_LEN];
+    let mut key = SecretKey::zeroed();
     pbkdf2::derive(
         pbkdf2::PBKDF2_HMAC_SHA512,
         NonZeroU32::new(100_000).unwrap(),
         &salt,
-        password.as_bytes(),
-        &mut key,
+        secret,
+        &mut *key,
     );
     key
 }
@@ -115,30 +349,92 @@ fn unbound_key(key: &Key) -> Result<aead::UnboundKey, UnspecifiedError> {
 
 /// Encrypt the plaintext in place using the specified key and incorporate the
 /// associated data (which is not encrypted). The plaintext is consumed during
-/// this process, even if it fails.
+/// this process, even if it fails, and wiped from memory.
 fn seal_in_place<A: AsRef<[u8]>>(
-    key: &Key,
+    key: aead::UnboundKey,
     aad: aead::Aad<A>,
-    mut plaintext: Vec<u8>,
+    plaintext: Vec<u8>,
 ) -> Result<(Nonce, Vec<u8>), UnspecifiedError> {
     use aead::BoundKey as _;
+    // Make room for the tag and nonce up front, so appending them doesn't
+    // leave a copy of the plaintext behind in freed memory.
+    let mut plaintext = Zeroizing::new(plaintext);
+    let mut buf = Vec::with_capacity(plaintext.len() + aead::MAX_TAG_LEN + LEGACY_NONCE_LEN);
+    buf.extend_from_slice(&plaintext);
+    plaintext.zeroize();
+    let mut plaintext = buf;
     let nonce = Nonce::random()?;
-    let mut key = aead::SealingKey::new(unbound_key(key)?, nonce.clone());
+    let mut key = aead::SealingKey::new(key, SingleUse(Some(nonce)));
     key.seal_in_place_append_tag(aad, &mut plaintext)
         .map_err(|_| UnspecifiedError {})?;
     Ok((nonce, plaintext))
 }
 
+/// Prefix the plaintext with its length and pad it with zeros. The plaintext
+/// is consumed during this process, even if it fails, and wiped from memory.
+fn pad(plaintext: Vec<u8>, padding: Padding) -> Result<Vec<u8>, UnspecifiedError> {
+    use std::convert::TryFrom as _;
+    let plaintext = Zeroizing::new(plaintext);
+    let len = u32::try_from(plaintext.len()).map_err(|_| UnspecifiedError {})?;
+    let padded_len = padding.padded_len(PADDING_HEADER_LEN + plaintext.len());
+    let mut padded = Vec::with_capacity(padded_len);
+    padded.extend_from_slice(&len.to_le_bytes());
+    padded.extend_from_slice(&plaintext);
+    padded.resize(padded_len, 0);
+    Ok(padded)
+}
+
+/// Remove the padding added by `pad` in place. The padded plaintext is
+/// consumed in this process, even if it fails.
+///
+/// Fails unless the padding is all zeros, so plaintext that was never padded
+/// isn't mistaken for padded plaintext.
+fn unpad(mut padded: Vec<u8>) -> Result<Vec<u8>, UnspecifiedError> {
+    use std::convert::TryInto as _;
+    let len = padded
+        .get(..PADDING_HEADER_LEN)
+        .and_then(|header| header.try_into().ok())
+        .map(|header| u32::from_le_bytes(header) as usize)
+        .filter(|&len| len <= padded.len() - PADDING_HEADER_LEN)
+        .filter(|&len| {
+            padded[PADDING_HEADER_LEN + len..]
+                .iter()
+                .all(|&byte| byte == 0)
+        });
+    let len = match len {
+        Some(len) => len,
+        None => {
+            padded.zeroize();
+            return Err(UnspecifiedError {});
+        }
+    };
+    padded.copy_within(PADDING_HEADER_LEN..PADDING_HEADER_LEN + len, 0);
+    padded[len..].zeroize();
+    padded.truncate(len);
+    Ok(padded)
+}
+
+/// Split the nonce appended by `seal_in_place`'s callers, which takes up
+/// `stored_len` bytes, off the end of the ciphertext. Fails if the ciphertext
+/// is too short to contain one.
+fn split_nonce(ciphertext: &mut Vec<u8>, stored_len: usize) -> Result<Nonce, UnspecifiedError> {
+    let at = ciphertext
+        .len()
+        .checked_sub(stored_len)
+        .ok_or(UnspecifiedError {})?;
+    Nonce::from_slice(&ciphertext.split_off(at)).ok_or(UnspecifiedError {})
+}
+
 /// Decrypt the ciphertext with the given key, associated data, and nonce in
 /// place. The ciphertext is consumed in this process, even if it fails.
 fn open_in_place<A: AsRef<[u8]>>(
-    key: &Key,
+    key: aead::UnboundKey,
     aad: aead::Aad<A>,
-    mut nonce: Nonce,
+    nonce: Nonce,
     mut ciphertext: Vec<u8>,
 ) -> Result<Vec<u8>, UnspecifiedError> {
     use aead::BoundKey as _;
-    let mut key = aead::OpeningKey::new(unbound_key(key)?, &mut nonce);
+    let mut key = aead::OpeningKey::new(key, SingleUse(Some(nonce)));
     let size = key
         .open_in_place(aad, &mut ciphertext)
         .map_err(|_| UnspecifiedError {})?
@@ -147,29 +443,100 @@ fn open_in_place<A: AsRef<[u8]>>(
     Ok(ciphertext)
 }
 
+/// How data is encrypted, which has changed between versions of the store's
+/// format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SealFormat {
+    /// Encrypted with the data key and the entry's id as associated data (v2)
+    Unpadded,
+    /// As `Unpadded`, but padded first (v3)
+    Padded,
+    /// As `Padded`, but with the record as associated data (v4)
+    Record,
+    /// As `Record`, but encrypted with a key derived from the data key for
+    /// each entry, draft, etc. and a nonce of the size that is used (v5)
+    Subkey,
+    /// As `Subkey`, but with the suite in front, which chooses the cipher and
+    /// is part of the key's derivation (v6)
+    Suite,
+}
+
+impl SealFormat {
+    /// Get the data that is authenticated along with the record's data in
+    /// this format.
+    fn aad(self, record: &Record) -> Vec<u8> {
+        match self {
+            SealFormat::Unpadded | SealFormat::Padded => record.id.to_bytes().to_vec(),
+            SealFormat::Record | SealFormat::Subkey | SealFormat::Suite => record.to_bytes(),
+        }
+    }
+}
+
+/// The kind of data in a record
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordKind {
+    /// The content of a journal entry
+    Content = 1,
+    /// The metadata of a journal entry
+    Metadata = 2,
+    /// A draft
+    Draft = 3,
+    /// The index of journal entries
+    Index = 4,
+}
+
+/// What a piece of encrypted data is. It is authenticated along with the
+/// data, so the data can't be opened as a different record, e.g. another
+/// entry's content or an older revision of the same entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record {
+    /// The id of the entry, draft, etc. the data belongs to
+    pub id: Uuid,
+    /// The kind of data
+    pub kind: RecordKind,
+    /// The revision of the data, which is incremented each time it changes
+    pub revision: u64,
+}
+
+impl Record {
+    /// Create a record for the specified revision of data.
+    pub fn new(id: Uuid, kind: RecordKind, revision: u64) -> Self {
+        Record { id, kind, revision }
+    }
+
+    /// Get the bytes that are authenticated along with the record's data.
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 + 1 + 8);
+        bytes.extend_from_slice(&self.id.to_bytes());
+        bytes.push(self.kind as u8);
+        bytes.extend_from_slice(&self.revision.to_le_bytes());
+        bytes
+    }
+}
+
 /// A type used to verify the username and password used to secure the database.
 #[derive(Debug)]
 pub struct CredentialGuard {
     /// The database's unique salt
     salt: DbSalt,
     /// The key derived from the user's name and password.
-    credential_key: Key,
+    credential_key: SecretKey,
 }
 
 impl CredentialGuard {
     /// Generate a new CredentialGuard from the database's unique salt and the user's name
-    /// and password.
-    pub fn new(salt: DbSalt, username: &str, password: &str) -> CredentialGuard {
-        let key = derive_key_from_credentials(&salt, username, password);
+    /// and secret.
+    pub fn new(salt: DbSalt, username: &str, secret: &[u8]) -> CredentialGuard {
+        let key = derive_key_from_credentials(&salt, username, secret);
         CredentialGuard {
             salt,
             credential_key: key,
         }
     }
 
-    /// Update the user's name and password
-    pub fn update_credentials(&mut self, username: &str, password: &str) {
-        self.credential_key = derive_key_from_credentials(&self.salt, username, password);
+    /// Update the user's name and secret
+    pub fn update_credentials(&mut self, username: &str, secret: &[u8]) {
+        self.credential_key = derive_key_from_credentials(&self.salt, username, secret);
     }
 
     /// Try to decrypt the key using the current user's name and password. If
@@ -179,24 +546,22 @@ impl CredentialGuard {
     /// be updated before calling this function again.
     pub fn try_decrypt_key(self, mut encrypted_key: Vec<u8>) -> Result<DataGuard, Self> {
         // If we can decrypt the key, the credentials are valid.
-        use std::convert::TryInto as _;
-        // Split the encrypted data from the nonce at the end.
-        let nonce_bytes = encrypted_key.split_off(encrypted_key.len() - Nonce::len());
-        let nonce = Nonce::from_le_bytes(nonce_bytes.try_into().unwrap());
-        if let Ok(key) = open_in_place(
-            &self.credential_key,
-            aead::Aad::empty(),
-            nonce,
-            encrypted_key,
-        ) {
-            // Replace the key derived from the user's credentials with the key
-            // we just decrypted. All further encryption should be done with
-            // this key.
-            Ok(DataGuard {
-                key: key.try_into().unwrap(),
-            })
-        } else {
-            Err(self)
+        let nonce = match split_nonce(&mut encrypted_key, LEGACY_NONCE_LEN) {
+            Ok(nonce) => nonce,
+            Err(_) => return Err(self),
+        };
+        let key = unbound_key(&self.credential_key)
+            .and_then(|key| open_in_place(key, aead::Aad::empty(), nonce, encrypted_key))
+            .map(Zeroizing::new);
+        // Replace the key derived from the user's credentials with the key we
+        // just decrypted. All further encryption should be done with this key.
+        match key.ok().and_then(|key| SecretKey::from_slice(&key)) {
+            Some(key) => Ok(DataGuard {
+                key,
+                padding: Padding::default(),
+                suite: Suite::default(),
+            }),
+            None => Err(self),
         }
     }
 
@@ -210,10 +575,23 @@ impl CredentialGuard {
         let mut buf = vec![0u8; KEY_LEN];
         SYSTEM_RNG.fill(&mut buf)?;
         assert!(buf.len() == KEY_LEN);
+        self.encrypt_key(buf)
+    }
+
+    /// Encrypt the key of an unlocked database with the current credentials,
+    /// so that they can be used to unlock it from now on.
+    pub fn encrypt_data_key(&self, guard: &DataGuard) -> Result<Vec<u8>, UnspecifiedError> {
+        self.encrypt_key(guard.key.to_vec())
+    }
+
+    /// Encrypt the key using the current credentials and append the nonce.
+    fn encrypt_key(&self, key: Vec<u8>) -> Result<Vec<u8>, UnspecifiedError> {
         let (nonce, mut encrypted_key) =
-            seal_in_place(&self.credential_key, aead::Aad::empty(), buf)?;
-        // Append the nonce to the end
-        encrypted_key.extend_from_slice(&nonce.to_le_bytes());
+            seal_in_place(unbound_key(&self.credential_key)?, aead::Aad::empty(), key)?;
+        // Append the nonce to the end, taking up as much space as it always
+        // has in key slots.
+        encrypted_key.extend_from_slice(&nonce.to_bytes());
+        encrypted_key.resize(encrypted_key.len() + LEGACY_NONCE_LEN - NONCE_LEN, 0);
         Ok(encrypted_key)
     }
 }
@@ -222,42 +600,138 @@ impl CredentialGuard {
 /// created from a CredentialGuard who's username and password have been verified.
 #[derive(Debug)]
 pub struct DataGuard {
-    key: Key,
+    key: SecretKey,
+    /// How plaintext is padded before it is encrypted
+    padding: Padding,
+    /// The cipher and keys that data is encrypted with
+    suite: Suite,
 }
 
 impl DataGuard {
-    /// Encrypt the plaintext associated with the Uuid in place using the
-    /// specified key. The plaintext is consumed during this process, even if it
-    /// fails.
+    /// Set how plaintext is padded before it is encrypted from now on. Data
+    /// that is already encrypted can be decrypted regardless.
+    pub fn set_padding(&mut self, padding: Padding) {
+        self.padding = padding;
+    }
+
+    /// Set the cipher and keys that data is encrypted with from now on. Data
+    /// that is already encrypted can be decrypted regardless.
+    pub fn set_suite(&mut self, suite: Suite) {
+        self.suite = suite;
+    }
+
+    /// Derive the key that the records of an entry, draft, etc. are encrypted
+    /// with from the data key and its id. Each of these keys encrypts far
+    /// less data than the data key would, so random nonces are much less
+    /// likely to ever be reused with the same key.
+    ///
+    /// Since v6, the suite is part of the derivation, so each cipher and key
+    /// generation has its own keys. Before, data was always encrypted with
+    /// AES-256-GCM.
+    fn subkey(&self, id: Uuid, suite: Option<Suite>) -> Result<aead::UnboundKey, UnspecifiedError> {
+        let id = id.to_bytes();
+        let suite_bytes = suite.map(Suite::to_bytes);
+        let info = [
+            SUBKEY_INFO,
+            &id[..],
+            suite_bytes.as_ref().map_or(&[], |bytes| &bytes[..]),
+        ];
+        let cipher = suite.map_or(Cipher::Aes256Gcm, |suite| suite.cipher);
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &[]).extract(&self.key[..]);
+        let okm = prk.expand(&info, cipher.algorithm())?;
+        Ok(aead::UnboundKey::from(okm))
+    }
+
+    /// Encrypt the plaintext of the record in place using the key of the
+    /// entry, draft, etc. it belongs to and the current suite. The plaintext
+    /// is padded first and consumed during this process, even if it fails.
     pub fn seal_in_place(
         &mut self,
-        uuid: Uuid,
+        record: &Record,
+        plaintext: Vec<u8>,
+    ) -> Result<Vec<u8>, UnspecifiedError> {
+        self.seal_in_format(SealFormat::Suite, record, plaintext)
+    }
+
+    /// Encrypt the plaintext of the record in place in the specified format,
+    /// e.g. while upgrading a store one version at a time. The plaintext is
+    /// consumed during this process, even if it fails.
+    pub fn seal_in_format(
+        &mut self,
+        format: SealFormat,
+        record: &Record,
         plaintext: Vec<u8>,
     ) -> Result<Vec<u8>, UnspecifiedError> {
-        let (nonce, mut encrypted_data) =
-            seal_in_place(&self.key, aead::Aad::from(uuid.to_bytes()), plaintext)?;
-        // Append the nonce to the end
-        encrypted_data.extend_from_slice(&nonce.to_le_bytes()[..]);
-        Ok(encrypted_data)
+        let plaintext = match format {
+            SealFormat::Unpadded => plaintext,
+            _ => pad(plaintext, self.padding)?,
+        };
+        let (key, nonce_len) = self.key_for(format, self.suite, record)?;
+        let (nonce, encrypted_data) =
+            seal_in_place(key, aead::Aad::from(format.aad(record)), plaintext)?;
+        // Put the suite in front, if the format has one, and append the nonce
+        // to the end, taking up as much space as it does in the format.
+        let mut sealed = Vec::with_capacity(SUITE_LEN + encrypted_data.len() + nonce_len);
+        if format == SealFormat::Suite {
+            sealed.extend_from_slice(&self.suite.to_bytes());
+        }
+        sealed.extend_from_slice(&encrypted_data);
+        sealed.extend_from_slice(&nonce.to_bytes());
+        sealed.resize(sealed.len() + nonce_len - NONCE_LEN, 0);
+        Ok(sealed)
     }
 
-    /// Decrypt the ciphertext with the given key, associated Uuid, and nonce in
-    /// place. The ciphertext is consumed in this process, even if it fails.
+    /// Decrypt the ciphertext of the record in place and remove its padding.
+    /// The ciphertext is consumed in this process, even if it fails.
     pub fn open_in_place(
         &mut self,
-        uuid: Uuid,
+        record: &Record,
+        ciphertext: Vec<u8>,
+    ) -> Result<Vec<u8>, UnspecifiedError> {
+        self.open_in_format(SealFormat::Suite, record, ciphertext)
+    }
+
+    /// Decrypt the ciphertext of the record, which was encrypted in the
+    /// specified format, in place. The ciphertext is consumed in this process,
+    /// even if it fails.
+    pub fn open_in_format(
+        &mut self,
+        format: SealFormat,
+        record: &Record,
         mut ciphertext: Vec<u8>,
     ) -> Result<Vec<u8>, UnspecifiedError> {
-        use std::convert::TryInto as _;
-        // Split the encrypted data from the nonce at the end.
-        let nonce_bytes = ciphertext.split_off(ciphertext.len() - Nonce::len());
-        let nonce = Nonce::from_le_bytes(nonce_bytes.try_into().unwrap());
-        open_in_place(
-            &self.key,
-            aead::Aad::from(uuid.to_bytes()),
-            nonce,
-            ciphertext,
-        )
+        // Data is decrypted with the suite it was encrypted with, which need
+        // not be the current one.
+        let suite = match format {
+            SealFormat::Suite => {
+                let suite = Suite::from_slice(&ciphertext).ok_or(UnspecifiedError {})?;
+                ciphertext.drain(..SUITE_LEN);
+                suite
+            }
+            _ => self.suite,
+        };
+        let (key, nonce_len) = self.key_for(format, suite, record)?;
+        let nonce = split_nonce(&mut ciphertext, nonce_len)?;
+        let plaintext = open_in_place(key, aead::Aad::from(format.aad(record)), nonce, ciphertext)?;
+        match format {
+            SealFormat::Unpadded => Ok(plaintext),
+            _ => unpad(plaintext),
+        }
+    }
+
+    /// Get the key that the record is encrypted with in the specified format
+    /// and suite, and how much space the nonce takes up.
+    fn key_for(
+        &self,
+        format: SealFormat,
+        suite: Suite,
+        record: &Record,
+    ) -> Result<(aead::UnboundKey, usize), UnspecifiedError> {
+        match format {
+            SealFormat::Suite => Ok((self.subkey(record.id, Some(suite))?, NONCE_LEN)),
+            SealFormat::Subkey => Ok((self.subkey(record.id, None)?, NONCE_LEN)),
+            _ => Ok((unbound_key(&self.key)?, LEGACY_NONCE_LEN)),
+        }
     }
 }
 
@@ -265,8 +739,8 @@ impl DataGuard {
 pub trait Seal: Sized {
     fn into_bytes(self) -> Vec<u8>;
 
-    fn seal(self, uuid: Uuid, guard: &mut DataGuard) -> Result<Vec<u8>, UnspecifiedError> {
-        guard.seal_in_place(uuid, self.into_bytes())
+    fn seal(self, record: &Record, guard: &mut DataGuard) -> Result<Vec<u8>, UnspecifiedError> {
+        guard.seal_in_place(record, self.into_bytes())
     }
 }
 
@@ -275,11 +749,11 @@ pub trait Open: Sized {
     fn from_bytes(bytes: Vec<u8>) -> Result<Self, UnspecifiedError>;
 
     fn open(
-        uuid: Uuid,
+        record: &Record,
         ciphertext: Vec<u8>,
         guard: &mut DataGuard,
     ) -> Result<Self, UnspecifiedError> {
-        let plaintext = guard.open_in_place(uuid, ciphertext)?;
+        let plaintext = guard.open_in_place(record, ciphertext)?;
         Open::from_bytes(plaintext)
     }
 }
@@ -302,9 +776,20 @@ impl Seal for String {
     }
 }
 
-impl Open for String {
+impl Seal for Zeroizing<String> {
+    fn into_bytes(mut self) -> Vec<u8> {
+        std::mem::take(&mut *self).into_bytes()
+    }
+}
+
+impl Open for Zeroizing<String> {
     fn from_bytes(bytes: Vec<u8>) -> Result<Self, UnspecifiedError> {
-        Ok(String::from_utf8(bytes).unwrap())
+        String::from_utf8(bytes)
+            .map(Zeroizing::new)
+            .map_err(|error| {
+                error.into_bytes().zeroize();
+                UnspecifiedError {}
+            })
     }
 }
 
@@ -318,11 +803,20 @@ impl Seal for time::OffsetDateTime {
 
 impl Open for time::OffsetDateTime {
     fn from_bytes(bytes: Vec<u8>) -> Result<Self, UnspecifiedError> {
-        Ok(time::OffsetDateTime::parse(
-            std::str::from_utf8(&bytes).unwrap(),
-            &time::format_description::well_known::Rfc3339,
-        )
-        .unwrap())
+        let text = std::str::from_utf8(&bytes).map_err(|_| UnspecifiedError {})?;
+        time::OffsetDateTime::parse(text, &time::format_description::well_known::Rfc3339)
+            .map_err(|_| UnspecifiedError {})
+    }
+}
+
+/// Get a guard with a fixed key, as if a journal had been unlocked, without
+/// the slow key derivation that unlocking one takes.
+#[cfg(test)]
+pub fn unlocked_guard() -> DataGuard {
+    DataGuard {
+        key: SecretKey::from_slice(&[7; KEY_LEN]).unwrap(),
+        padding: Padding::default(),
+        suite: Suite::default(),
     }
 }
 
@@ -336,12 +830,233 @@ mod test {
         let username = "username";
         let password = "password";
         let salt = generate_db_salt().unwrap();
-        let credential_key = derive_key_from_credentials(&salt, username, password);
+        let credential_key = derive_key_from_credentials(&salt, username, password.as_bytes());
 
         let data = message.to_vec();
-        let (nonce, ciphertext) = seal_in_place(&credential_key, aead::Aad::empty(), data).unwrap();
-        let extracted =
-            open_in_place(&credential_key, aead::Aad::empty(), nonce, ciphertext).unwrap();
+        assert_eq!(format!("{:?}", credential_key), "SecretKey(..)");
+        let key = || unbound_key(&credential_key).unwrap();
+        let (nonce, ciphertext) = seal_in_place(key(), aead::Aad::empty(), data).unwrap();
+        let extracted = open_in_place(key(), aead::Aad::empty(), nonce, ciphertext).unwrap();
         assert_eq!(message, &*extracted);
     }
+
+    #[test]
+    fn credential_secret_depends_on_unlock_mode() {
+        let keyfile = b"keyfile contents";
+        // Journals that only use a password must still unlock.
+        assert_eq!(*credential_secret(Some("password"), None), b"password");
+        let keyfile_only = credential_secret(None, Some(keyfile));
+        let both = credential_secret(Some("password"), Some(keyfile));
+        assert_eq!(keyfile_only.len(), digest::SHA256_OUTPUT_LEN);
+        assert_ne!(both, keyfile_only);
+        assert_ne!(both, credential_secret(Some("password"), Some(b"other")));
+    }
+
+    #[test]
+    fn data_key_can_be_reencrypted() {
+        let salt = generate_db_salt().unwrap();
+        let old = CredentialGuard::new(salt, "username", b"old");
+        let encrypted_key = old.generate_encrypted_key().unwrap();
+        let guard = old.try_decrypt_key(encrypted_key).unwrap();
+
+        let new = CredentialGuard::new(salt, "username", b"new");
+        let encrypted_key = new.encrypt_data_key(&guard).unwrap();
+        let reopened = new.try_decrypt_key(encrypted_key).unwrap();
+        assert_eq!(*guard.key, *reopened.key);
+    }
+
+    #[test]
+    fn padding_hides_plaintext_size() {
+        let mut guard = unlocked_guard();
+        let record = Record::new(Uuid::random().unwrap(), RecordKind::Content, 0);
+
+        for (padding, short_len, long_len) in [
+            (Padding::None, 5, 300),
+            (Padding::PowerOfTwo, 256, 512),
+            (Padding::Bucket4K, 4096, 4096),
+            (Padding::Bucket64K, 65536, 65536),
+        ] {
+            guard.set_padding(padding);
+            for (plaintext, len) in [(&b"short"[..], short_len), (&[7u8; 300][..], long_len)] {
+                let ciphertext = guard.seal_in_place(&record, plaintext.to_vec()).unwrap();
+                let overhead = SUITE_LEN + aead::MAX_TAG_LEN + NONCE_LEN;
+                let header = if padding == Padding::None {
+                    PADDING_HEADER_LEN
+                } else {
+                    0
+                };
+                assert_eq!(ciphertext.len(), len + header + overhead, "{}", padding);
+                assert_eq!(guard.open_in_place(&record, ciphertext).unwrap(), plaintext);
+            }
+        }
+        assert!(unpad(vec![1, 2]).is_err());
+        assert!(unpad(vec![9, 0, 0, 0, 1, 2]).is_err());
+        assert!(unpad(vec![1, 0, 0, 0, 1, 2]).is_err());
+        assert_eq!(unpad(vec![1, 0, 0, 0, 1, 0]).unwrap(), [1]);
+    }
+
+    #[test]
+    fn records_are_bound_to_their_data() {
+        let mut guard = unlocked_guard();
+        let record = Record::new(Uuid::random().unwrap(), RecordKind::Content, 1);
+
+        let ciphertext = guard.seal_in_place(&record, b"Hello".to_vec()).unwrap();
+        for other in [
+            Record::new(Uuid::random().unwrap(), record.kind, record.revision),
+            Record::new(record.id, RecordKind::Metadata, record.revision),
+            Record::new(record.id, record.kind, 0),
+        ] {
+            assert!(guard.open_in_place(&other, ciphertext.clone()).is_err());
+        }
+        assert!(guard
+            .open_in_format(SealFormat::Record, &record, ciphertext.clone())
+            .is_err());
+        assert_eq!(guard.open_in_place(&record, ciphertext).unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn older_formats_can_be_opened() {
+        let mut guard = unlocked_guard();
+        let record = Record::new(Uuid::random().unwrap(), RecordKind::Content, 0);
+
+        for format in [
+            SealFormat::Unpadded,
+            SealFormat::Padded,
+            SealFormat::Record,
+            SealFormat::Subkey,
+        ] {
+            let ciphertext = guard
+                .seal_in_format(format, &record, b"Hello".to_vec())
+                .unwrap();
+            // Formats before v5 have a 16 byte nonce at the end.
+            if format != SealFormat::Subkey {
+                assert_eq!(ciphertext[ciphertext.len() - 4..], [0; 4]);
+            }
+            let opened = guard.open_in_format(format, &record, ciphertext.clone());
+            assert_eq!(opened.unwrap(), b"Hello", "{:?}", format);
+            assert!(guard.open_in_place(&record, ciphertext).is_err());
+        }
+    }
+
+    #[test]
+    fn suites_can_be_mixed() {
+        let mut guard = unlocked_guard();
+        let record = Record::new(Uuid::random().unwrap(), RecordKind::Content, 0);
+
+        let old = guard.seal_in_place(&record, b"Hello".to_vec()).unwrap();
+        guard.set_suite(Suite {
+            cipher: Cipher::ChaCha20Poly1305,
+            key_generation: 1,
+        });
+        let new = guard.seal_in_place(&record, b"Hello".to_vec()).unwrap();
+        assert_eq!(old.len(), new.len());
+        assert_ne!(old[..SUITE_LEN], new[..SUITE_LEN]);
+        for ciphertext in [old, new] {
+            let opened = guard.open_in_place(&record, ciphertext.clone()).unwrap();
+            assert_eq!(opened, b"Hello");
+            // The suite can't be changed to decrypt data with another key.
+            let mut other_cipher = ciphertext.clone();
+            other_cipher[0] = if other_cipher[0] == Cipher::Aes256Gcm.id() {
+                Cipher::ChaCha20Poly1305.id()
+            } else {
+                Cipher::Aes256Gcm.id()
+            };
+            assert!(guard.open_in_place(&record, other_cipher).is_err());
+            let mut other_generation = ciphertext;
+            other_generation[1] ^= 1;
+            assert!(guard.open_in_place(&record, other_generation).is_err());
+        }
+        assert_eq!("chacha20-poly1305".parse(), Ok(Cipher::ChaCha20Poly1305));
+        assert!("aes".parse::<Cipher>().is_err());
+    }
+
+    /// Pass truncated and bit-flipped copies of valid input, and random input,
+    /// to `open`, which must not panic. Some of the input may still be valid,
+    /// since key slots only use 12 of the 16 bytes stored for the nonce.
+    ///
+    /// The random input comes from a fixed seed, so failures can be
+    /// reproduced. The `fuzz` directory has targets for finding more.
+    fn fuzz<F: FnMut(Vec<u8>)>(valid: &[u8], mut open: F) {
+        for len in 0..valid.len() {
+            open(valid[..len].to_vec());
+        }
+        for bit in 0..valid.len() * 8 {
+            let mut input = valid.to_vec();
+            input[bit / 8] ^= 1 << (bit % 8);
+            open(input);
+        }
+        let mut extended = valid.to_vec();
+        extended.push(0);
+        open(extended);
+        // xorshift64
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..1000 {
+            let len = next() as usize % 512;
+            let input = (0..len).map(|_| next() as u8).collect();
+            open(input);
+        }
+    }
+
+    #[test]
+    fn corrupt_data_is_an_error() {
+        let mut guard = unlocked_guard();
+        let record = Record::new(Uuid::random().unwrap(), RecordKind::Content, 0);
+
+        let ciphertext = guard
+            .seal_in_place(&record, b"Hello, World".to_vec())
+            .unwrap();
+        fuzz(&ciphertext, |input| {
+            assert!(guard.open_in_place(&record, input).is_err());
+        });
+
+        // Data that decrypts but isn't what it should be is an error too.
+        for plaintext in [vec![], vec![0xff; 8], vec![16, 0, 0, 0, 0xff, 0xfe]] {
+            let key = guard.subkey(record.id, Some(guard.suite)).unwrap();
+            let (nonce, encrypted_data) =
+                seal_in_place(key, aead::Aad::from(record.to_bytes()), plaintext).unwrap();
+            let mut ciphertext = guard.suite.to_bytes().to_vec();
+            ciphertext.extend_from_slice(&encrypted_data);
+            ciphertext.extend_from_slice(&nonce.to_bytes());
+            assert!(guard.open_in_place(&record, ciphertext).is_err());
+        }
+        let ciphertext = guard.seal_in_place(&record, b"\xff\xfe".to_vec()).unwrap();
+        assert!(Zeroizing::<String>::open(&record, ciphertext, &mut guard).is_err());
+        for plaintext in [&b"\xff\xfe"[..], b"yesterday"] {
+            let ciphertext = guard.seal_in_place(&record, plaintext.to_vec()).unwrap();
+            assert!(time::OffsetDateTime::open(&record, ciphertext, &mut guard).is_err());
+        }
+    }
+
+    #[test]
+    fn corrupt_key_is_an_error() {
+        let salt = generate_db_salt().unwrap();
+        let cred_guard = CredentialGuard::new(salt, "username", b"password");
+        let encrypted_key = cred_guard.generate_encrypted_key().unwrap();
+        // Deriving the credential key again is slow, so copy it instead.
+        let credential_key = *cred_guard.credential_key;
+        let new_cred_guard = || CredentialGuard {
+            salt,
+            credential_key: SecretKey::from_slice(&credential_key).unwrap(),
+        };
+        let key = cred_guard
+            .try_decrypt_key(encrypted_key.clone())
+            .unwrap()
+            .key;
+        let mut cred_guard = Some(new_cred_guard());
+        fuzz(&encrypted_key, |input| {
+            cred_guard = match cred_guard.take().unwrap().try_decrypt_key(input) {
+                Ok(guard) => {
+                    assert_eq!(*guard.key, *key);
+                    Some(new_cred_guard())
+                }
+                Err(cred_guard) => Some(cred_guard),
+            };
+        });
+    }
 }
@@ -0,0 +1,311 @@
+use ring::digest;
+use zeroize::{Zeroize as _, Zeroizing};
+
+use crate::security::{UnspecifiedError, SYSTEM_RNG};
+
+/// Multiply two elements of GF(2^8), using the same reducing polynomial as AES
+/// (x^8 + x^4 + x^3 + x + 1).
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Get the multiplicative inverse of a non-zero element of GF(2^8), which is
+/// a^254.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+    let mut exponent = 254u8;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = gf_mul(result, power);
+        }
+        power = gf_mul(power, power);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// One share of a secret split with Shamir's secret sharing. Any `threshold`
+/// shares of the same set can rebuild the secret, fewer reveal nothing about
+/// it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    /// Identifies the set of shares the share belongs to
+    set: u16,
+    /// The number of shares needed to rebuild the secret
+    threshold: u8,
+    /// The point the secret's polynomials were evaluated at, never 0
+    x: u8,
+    /// The polynomials evaluated at `x`, one for each byte of the secret
+    data: Vec<u8>,
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+impl Share {
+    /// The number of bytes of the checksum at the end of an encoded share
+    const CHECKSUM_LEN: usize = 2;
+
+    /// Get the number of shares needed to rebuild the secret.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Get the number of the share within its set, starting from 1.
+    pub fn number(&self) -> u8 {
+        self.x
+    }
+
+    /// Encode the share as bytes, with a checksum at the end.
+    fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut bytes =
+            Zeroizing::new(Vec::with_capacity(4 + self.data.len() + Self::CHECKSUM_LEN));
+        bytes.extend(self.set.to_be_bytes());
+        bytes.push(self.threshold);
+        bytes.push(self.x);
+        bytes.extend(&self.data);
+        let checksum = digest::digest(&digest::SHA256, &bytes);
+        bytes.extend(&checksum.as_ref()[..Self::CHECKSUM_LEN]);
+        bytes
+    }
+
+    /// Decode a share encoded with `to_bytes`.
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Share> {
+        if bytes.len() < 5 + Self::CHECKSUM_LEN {
+            anyhow::bail!("The share is too short");
+        }
+        let (bytes, checksum) = bytes.split_at(bytes.len() - Self::CHECKSUM_LEN);
+        if digest::digest(&digest::SHA256, bytes).as_ref()[..Self::CHECKSUM_LEN] != *checksum {
+            anyhow::bail!("The share has a typo");
+        }
+        let share = Share {
+            set: u16::from_be_bytes([bytes[0], bytes[1]]),
+            threshold: bytes[2],
+            x: bytes[3],
+            data: bytes[4..].to_vec(),
+        };
+        if share.x == 0 || share.threshold == 0 {
+            anyhow::bail!("The share is invalid");
+        }
+        Ok(share)
+    }
+}
+
+impl std::fmt::Display for Share {
+    /// Write the share as a list of words, one for each byte.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let words: Vec<_> = self
+            .to_bytes()
+            .iter()
+            .map(|&byte| WORDS[byte as usize])
+            .collect();
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+impl std::str::FromStr for Share {
+    type Err = anyhow::Error;
+
+    /// Parse a share written as a list of words. Case, whitespace and lines
+    /// starting with `#` are ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(str::split_whitespace)
+            .map(|word| {
+                let word = word.to_ascii_lowercase();
+                WORDS
+                    .iter()
+                    .position(|&w| w == word)
+                    .map(|byte| byte as u8)
+                    .ok_or_else(|| anyhow::anyhow!("{:?} is not a share word", word))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map(Zeroizing::new)?;
+        Share::from_bytes(&bytes)
+    }
+}
+
+/// Split the secret into `count` shares, any `threshold` of which can rebuild
+/// it.
+pub fn split(secret: &[u8], threshold: u8, count: u8) -> anyhow::Result<Vec<Share>> {
+    use ring::rand::SecureRandom as _;
+
+    if threshold == 0 || threshold > count {
+        anyhow::bail!("The threshold must be between 1 and the number of shares");
+    }
+    let mut set = [0u8; 2];
+    SYSTEM_RNG.fill(&mut set).map_err(UnspecifiedError::from)?;
+    let mut shares: Vec<_> = (1..=count)
+        .map(|x| Share {
+            set: u16::from_be_bytes(set),
+            threshold,
+            x,
+            data: Vec::with_capacity(secret.len()),
+        })
+        .collect();
+    // Each byte of the secret is the constant term of a random polynomial of
+    // degree threshold - 1. Each share holds the polynomials evaluated at a
+    // different point.
+    let mut coefficients = Zeroizing::new(vec![0u8; threshold as usize]);
+    for &byte in secret {
+        coefficients[0] = byte;
+        SYSTEM_RNG
+            .fill(&mut coefficients[1..])
+            .map_err(UnspecifiedError::from)?;
+        for share in &mut shares {
+            // Evaluate with Horner's method.
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0, |y, &coefficient| gf_mul(y, share.x) ^ coefficient);
+            share.data.push(y);
+        }
+    }
+    Ok(shares)
+}
+
+/// Rebuild the secret from shares of the same set. There must be at least as
+/// many different shares as the set's threshold.
+pub fn combine(shares: &[Share]) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+    let first = shares
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No shares given"))?;
+    let mut unique: Vec<&Share> = Vec::new();
+    for share in shares {
+        if share.set != first.set
+            || share.threshold != first.threshold
+            || share.data.len() != first.data.len()
+        {
+            anyhow::bail!("The shares are not all from the same set");
+        }
+        match unique.iter().find(|other| other.x == share.x) {
+            Some(other) if other.data != share.data => {
+                anyhow::bail!("There are two different shares numbered {}", share.x)
+            }
+            Some(_) => (),
+            None => unique.push(share),
+        }
+    }
+    if unique.len() < first.threshold as usize {
+        anyhow::bail!(
+            "{} different shares are needed, only {} were given",
+            first.threshold,
+            unique.len()
+        );
+    }
+    let unique = &unique[..first.threshold as usize];
+
+    // Evaluate the polynomials at 0 with Lagrange interpolation. Addition and
+    // subtraction are both XOR in GF(2^8).
+    let weights: Vec<u8> = unique
+        .iter()
+        .map(|share| {
+            unique
+                .iter()
+                .filter(|other| other.x != share.x)
+                .fold(1, |weight, other| {
+                    gf_mul(weight, gf_mul(other.x, gf_inv(other.x ^ share.x)))
+                })
+        })
+        .collect();
+    Ok(Zeroizing::new(
+        (0..first.data.len())
+            .map(|i| {
+                unique
+                    .iter()
+                    .zip(&weights)
+                    .fold(0, |secret, (share, &weight)| {
+                        secret ^ gf_mul(share.data[i], weight)
+                    })
+            })
+            .collect(),
+    ))
+}
+
+/// The words shares are written with, one for each possible byte
+const WORDS: [&str; 256] = [
+    "able", "acid", "aged", "area", "army", "baby", "back", "ball", "bank", "base", "bear", "beat",
+    "belt", "bird", "blow", "boat", "body", "book", "boot", "boss", "both", "bowl", "burn", "bush",
+    "cake", "calm", "camp", "care", "cart", "cash", "cast", "chat", "chip", "city", "club", "coal",
+    "code", "cold", "cool", "copy", "core", "cost", "crew", "dark", "data", "date", "days", "deal",
+    "deep", "deer", "dial", "diet", "dish", "dock", "door", "down", "draw", "drum", "duck", "dust",
+    "each", "earn", "east", "easy", "else", "envy", "epic", "exam", "exit", "fact", "fair", "fall",
+    "fast", "fear", "feel", "file", "find", "fine", "fire", "fish", "five", "flat", "flow", "food",
+    "foot", "fork", "fort", "four", "frog", "fuel", "full", "gain", "game", "gear", "gift", "give",
+    "glad", "glow", "goat", "gold", "good", "grab", "grid", "grow", "gulf", "half", "hall", "hang",
+    "hard", "harp", "head", "heat", "hero", "hill", "hold", "hole", "home", "hope", "horn", "hour",
+    "huge", "idea", "inch", "iron", "jazz", "join", "jump", "jury", "keep", "kick", "kind", "kite",
+    "knee", "lake", "lamp", "land", "last", "lava", "lead", "leaf", "life", "lift", "lime", "link",
+    "lion", "load", "loan", "long", "loop", "lord", "luck", "lung", "main", "make", "mask", "meal",
+    "meat", "mild", "milk", "mine", "mint", "mist", "moon", "moss", "move", "myth", "name", "navy",
+    "neck", "news", "nice", "noon", "nose", "note", "oven", "pace", "page", "pain", "palm", "park",
+    "path", "pear", "pine", "pipe", "plan", "plot", "plum", "poem", "pond", "pool", "pose", "post",
+    "pump", "pure", "quiz", "rail", "rain", "rare", "rear", "rice", "ride", "ring", "rock", "roof",
+    "root", "rope", "rose", "rule", "rush", "sail", "salt", "save", "seal", "seat", "ship", "shoe",
+    "side", "sign", "silk", "site", "size", "slow", "snow", "sock", "soft", "soil", "soup", "star",
+    "stem", "tail", "tank", "task", "team", "test", "tide", "time", "tone", "tool", "town", "tree",
+    "tube", "tune", "unit", "vase", "vast", "vest", "view", "vote", "wage", "wave", "wolf", "wood",
+    "word", "work", "year", "zero",
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gf_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn any_threshold_shares_rebuild_the_secret() {
+        let secret = b"a secret of twenty b".to_vec();
+        let shares = split(&secret, 3, 5).unwrap();
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset = [shares[c].clone(), shares[a].clone(), shares[b].clone()];
+                    assert_eq!(*combine(&subset).unwrap(), secret);
+                }
+            }
+        }
+        assert!(combine(&shares[..2]).is_err());
+        assert!(combine(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err());
+        let other = split(&secret, 3, 5).unwrap();
+        assert!(combine(&[shares[0].clone(), shares[1].clone(), other[2].clone()]).is_err());
+    }
+
+    #[test]
+    fn shares_round_trip_as_words() {
+        let words: std::collections::HashSet<_> = WORDS.iter().collect();
+        assert_eq!(words.len(), WORDS.len());
+
+        let share = split(b"secret", 2, 3).unwrap().remove(1);
+        let written = format!("# Share 2 of 3\n{}\n", share.to_string().to_uppercase());
+        assert_eq!(written.parse::<Share>().unwrap(), share);
+
+        let mut typo: Vec<_> = share.to_string().split(' ').map(String::from).collect();
+        typo[5] = if typo[5] == "able" { "acid" } else { "able" }.to_string();
+        assert!(typo.join(" ").parse::<Share>().is_err());
+    }
+}
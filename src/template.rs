@@ -0,0 +1,139 @@
+use anyhow::Context as _;
+use fs_err as fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::db::now_local;
+use crate::security::{UnspecifiedError, SYSTEM_RNG};
+
+/// Find the file for the named template in the templates directory.
+///
+/// The name may be the file's full name (`daily.md`) or its name without an
+/// extension (`daily`).
+fn find_template_path(cfg: &Config, name: &str) -> anyhow::Result<PathBuf> {
+    let dir = cfg.templates_dir_path();
+    let path = dir.join(name);
+    if path.is_file() {
+        return Ok(path);
+    }
+    if dir.is_dir() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.file_stem() == Some(name.as_ref()) {
+                return Ok(path);
+            }
+        }
+    }
+    Err(anyhow::anyhow!(
+        "Could not find template {:?} in {}",
+        name,
+        dir.display()
+    ))
+}
+
+/// Pick a random writing prompt from the prompts file. Returns an empty string
+/// if there are no prompts.
+fn random_prompt(cfg: &Config) -> anyhow::Result<String> {
+    use ring::rand::SecureRandom as _;
+
+    let path = cfg.prompts_file_path();
+    if !path.exists() {
+        return Ok(String::new());
+    }
+    let prompts = fs::read_to_string(&path)?;
+    let prompts: Vec<_> = prompts
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if prompts.is_empty() {
+        return Ok(String::new());
+    }
+    let mut buf = [0u8; 4];
+    SYSTEM_RNG.fill(&mut buf).map_err(UnspecifiedError::from)?;
+    let index = u32::from_le_bytes(buf) as usize % prompts.len();
+    Ok(prompts[index].to_string())
+}
+
+/// Replace every `{name}` in the template with the value of the variable of
+/// the same name. Unknown variables are left as they are. Only the template
+/// itself is searched, so values are never expanded in turn.
+fn render(template: &str, variables: &[(&str, String)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let variable = rest.find('}').and_then(|end| {
+            variables
+                .iter()
+                .find(|(name, _)| *name == &rest[1..end])
+                .map(|(_, value)| (end, value))
+        });
+        match variable {
+            Some((end, value)) => {
+                rendered.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Load the named template and fill in its variables for an entry by the
+/// specified author.
+pub fn render_template(cfg: &Config, name: &str, author: &str) -> anyhow::Result<String> {
+    let path = find_template_path(cfg, name)?;
+    let template = fs::read_to_string(&path)?;
+    let now = now_local();
+    let mut variables = vec![
+        (
+            "date",
+            now.format(&time::format_description::parse(cfg.date_format())?)?,
+        ),
+        ("weekday", now.weekday().to_string()),
+        ("author", author.to_string()),
+    ];
+    if template.contains("{prompt}") {
+        variables.push((
+            "prompt",
+            random_prompt(cfg).context("Could not pick a writing prompt")?,
+        ));
+    }
+    Ok(render(&template, &variables))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_replaces_known_variables() {
+        let rendered = render(
+            "# {weekday} {date}\n{prompt}\n{unknown}",
+            &[
+                ("date", "2001-07-08".to_string()),
+                ("weekday", "Sunday".to_string()),
+                ("prompt", "What went well?".to_string()),
+            ],
+        );
+        assert_eq!(rendered, "# Sunday 2001-07-08\nWhat went well?\n{unknown}");
+    }
+
+    #[test]
+    fn render_does_not_expand_values() {
+        let rendered = render(
+            "{prompt} {{date}} {date",
+            &[
+                ("date", "2001-07-08".to_string()),
+                ("prompt", "What happened on {date}?".to_string()),
+            ],
+        );
+        assert_eq!(rendered, "What happened on {date}? {2001-07-08} {date");
+    }
+}
@@ -6,6 +6,9 @@ use crate::security::{UnspecifiedError, SYSTEM_RNG};
 pub struct Uuid(u128);
 
 impl Uuid {
+    /// The Uuid of all zeros, used for data that isn't an entry or draft.
+    pub const NIL: Uuid = Uuid(0);
+
     /// Generate a new, random Uuid
     pub fn random() -> Result<Self, UnspecifiedError> {
         use ring::rand::SecureRandom as _;
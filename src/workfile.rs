@@ -0,0 +1,150 @@
+use anyhow::Context as _;
+use std::io::{Read as _, Seek as _, Write as _};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+use crate::config::Config;
+
+/// A temporary file holding plaintext while it is being edited.
+///
+/// The file is only readable/writable by the current user and is overwritten
+/// before it is deleted.
+pub struct WorkFile {
+    file: tempfile::NamedTempFile,
+}
+
+impl WorkFile {
+    /// Create a new, empty work file in the working directory specified by
+    /// config.
+    pub fn new(cfg: &Config) -> anyhow::Result<WorkFile> {
+        let dir = get_work_dir(cfg);
+        let file = tempfile::NamedTempFile::new_in(&dir)
+            .context(format!("Could not create temp file in {}", dir.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            file.as_file()
+                .set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(WorkFile { file })
+    }
+
+    /// Get the path to the work file.
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Replace the contents of the work file.
+    pub fn write(&mut self, contents: &str) -> anyhow::Result<()> {
+        let file = self.file.as_file_mut();
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Read the contents of the work file.
+    ///
+    /// The file is reopened by path, since editors often replace the file
+    /// rather than writing to it.
+    pub fn read(&self) -> anyhow::Result<Zeroizing<String>> {
+        let mut contents = Zeroizing::new(String::new());
+        std::fs::File::open(self.path())
+            .context(format!(
+                "Could not open temp file: {}",
+                self.path().display()
+            ))?
+            .read_to_string(&mut contents)
+            .context(format!(
+                "Failed to read from temp file: {}",
+                self.path().display()
+            ))?;
+        Ok(contents)
+    }
+
+    /// Overwrite the contents of the file at the work file's path with zeros.
+    fn overwrite(&self) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new().write(true).open(self.path())?;
+        let len = file.metadata()?.len();
+        let zeros = [0u8; 4096];
+        let mut remaining = len;
+        while remaining > 0 {
+            let n = remaining.min(zeros.len() as u64) as usize;
+            file.write_all(&zeros[..n])?;
+            remaining -= n as u64;
+        }
+        file.sync_data()
+    }
+}
+
+impl Drop for WorkFile {
+    fn drop(&mut self) {
+        // The file itself is removed when the NamedTempFile is dropped.
+        if let Err(e) = self.overwrite() {
+            log::warn!("Could not overwrite {}: {}", self.path().display(), e);
+        }
+    }
+}
+
+/// Get the directory to create work files in.
+///
+/// If config asks for work files to be kept off disk, a RAM-backed directory
+/// is preferred and a warning is printed if none is available.
+fn get_work_dir(cfg: &Config) -> PathBuf {
+    if !cfg.secure_temp.unwrap_or(false) {
+        return cfg.temp_dir.clone().unwrap_or_else(std::env::temp_dir);
+    }
+
+    if let Some(temp_dir) = &cfg.temp_dir {
+        if !is_ram_backed(temp_dir) {
+            eprintln!(
+                "Warning: {} is not RAM-backed. Plaintext may be written to disk.",
+                temp_dir.display()
+            );
+        }
+        return temp_dir.clone();
+    }
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(std::iter::once(PathBuf::from("/dev/shm")))
+        .find(|dir| dir.is_dir() && is_ram_backed(dir))
+        .unwrap_or_else(|| {
+            let dir = std::env::temp_dir();
+            eprintln!(
+                "Warning: No RAM-backed directory is available, using {}. Plaintext may be written to disk.",
+                dir.display()
+            );
+            dir
+        })
+}
+
+/// Whether the directory is on a RAM-backed file system.
+#[cfg(target_os = "linux")]
+fn is_ram_backed(dir: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    // The magic numbers are 32 bits, but `f_type`'s type differs between
+    // platforms, so compare only its low 32 bits.
+    const TMPFS_MAGIC: u32 = 0x0102_1994;
+    const RAMFS_MAGIC: u32 = 0x8584_58f6;
+
+    let path = match std::ffi::CString::new(dir.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    // SAFETY: `path` is a valid, nul-terminated string and `stat` is a valid
+    // statfs struct for the duration of the call.
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return false;
+    }
+    matches!(stat.f_type as u32, TMPFS_MAGIC | RAMFS_MAGIC)
+}
+
+/// Whether the directory is on a RAM-backed file system.
+#[cfg(not(target_os = "linux"))]
+fn is_ram_backed(_dir: &Path) -> bool {
+    false
+}